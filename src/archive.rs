@@ -0,0 +1,211 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Read},
+    os::unix::fs::OpenOptionsExt,
+    path::{Component, Path, PathBuf},
+};
+
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use nix::libc;
+
+pub struct ExtractLimit {
+    pub max_entries: usize,
+    pub max_size: u64,
+}
+
+pub fn is_archive(path: &str) -> bool {
+    [".tar", ".tar.gz", ".tgz", ".zip"]
+        .iter()
+        .any(|extension| path.ends_with(extension))
+}
+
+struct Extractor<'a> {
+    target: &'a Path,
+    limit: &'a ExtractLimit,
+    entries: usize,
+    size: u64,
+    files: Vec<PathBuf>,
+}
+
+fn sanitize(name: &Path) -> Result<PathBuf, String> {
+    let mut path = PathBuf::new();
+
+    for component in name.components() {
+        match component {
+            Component::Normal(part) => path.push(part),
+            Component::CurDir => {}
+            _ => return Err(format!("unsafe path in archive: {}", name.display())),
+        }
+    }
+
+    Ok(path)
+}
+
+impl<'a> Extractor<'a> {
+    fn count_entry(&mut self) -> Result<(), String> {
+        self.entries += 1;
+        if self.entries > self.limit.max_entries {
+            return Err(format!(
+                "archive has more than {} entries",
+                self.limit.max_entries
+            ));
+        }
+
+        Ok(())
+    }
+
+    fn dir(&mut self, name: &Path) -> Result<(), String> {
+        self.count_entry()?;
+
+        let path = self.target.join(sanitize(name)?);
+        fs::create_dir_all(&path).map_err(|err| err.to_string())
+    }
+
+    fn file(&mut self, name: &Path, mode: u32, reader: &mut dyn Read) -> Result<(), String> {
+        self.count_entry()?;
+
+        let relative = sanitize(name)?;
+        if relative.as_os_str().is_empty() {
+            return Err(format!("empty file name in archive: {}", name.display()));
+        }
+
+        let path = self.target.join(&relative);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(|err| err.to_string())?;
+        }
+
+        // create_new refuses to follow or overwrite anything already on disk
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(mode & 0o755)
+            .open(&path)
+            .map_err(|err| format!("{}: {}", relative.display(), err))?;
+
+        // declared sizes can lie, so the limit is enforced on the bytes actually written
+        let remaining = self.limit.max_size - self.size;
+        let written = io::copy(&mut reader.take(remaining + 1), &mut file)
+            .map_err(|err| format!("{}: {}", relative.display(), err))?;
+        if written > remaining {
+            return Err(format!(
+                "archive expands to more than {} bytes",
+                self.limit.max_size
+            ));
+        }
+
+        self.size += written;
+        self.files.push(relative);
+
+        Ok(())
+    }
+
+    fn tar(&mut self, reader: impl Read) -> Result<(), String> {
+        let mut archive = tar::Archive::new(reader);
+
+        for entry in archive.entries().map_err(|err| err.to_string())? {
+            let mut entry = entry.map_err(|err| err.to_string())?;
+            let name = entry.path().map_err(|err| err.to_string())?.to_path_buf();
+            let mode = entry.header().mode().unwrap_or(0o644);
+
+            match entry.header().entry_type() {
+                tar::EntryType::Directory => self.dir(&name)?,
+                tar::EntryType::Regular | tar::EntryType::Continuous => {
+                    self.file(&name, mode, &mut entry)?
+                }
+                entry_type => {
+                    return Err(format!(
+                        "unsupported archive entry {:?}: {}",
+                        entry_type,
+                        name.display()
+                    ))
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn zip(&mut self, file: File) -> Result<(), String> {
+        let mut archive = zip::ZipArchive::new(file).map_err(|err| err.to_string())?;
+
+        for i in 0..archive.len() {
+            let mut entry = archive.by_index(i).map_err(|err| err.to_string())?;
+            let name = PathBuf::from(entry.name());
+            let mode = entry.unix_mode().unwrap_or(0o644);
+
+            if mode & libc::S_IFMT == libc::S_IFLNK {
+                return Err(format!(
+                    "unsupported archive entry symlink: {}",
+                    name.display()
+                ));
+            }
+
+            if entry.is_dir() {
+                self.dir(&name)?;
+            } else {
+                self.file(&name, mode, &mut entry)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+pub fn extract(
+    archive: &Path,
+    target: &Path,
+    limit: &ExtractLimit,
+) -> Result<Vec<PathBuf>, String> {
+    let file = File::open(archive).map_err(|err| err.to_string())?;
+
+    // a fresh directory guarantees that no planted symlink can redirect the extraction
+    fs::create_dir(target).map_err(|err| format!("{}: {}", target.display(), err))?;
+
+    let mut extractor = Extractor {
+        target,
+        limit,
+        entries: 0,
+        size: 0,
+        files: vec![],
+    };
+
+    let name = archive.to_string_lossy();
+    let result = if name.ends_with(".zip") {
+        extractor.zip(file)
+    } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+        extractor.tar(GzDecoder::new(file))
+    } else {
+        extractor.tar(file)
+    };
+
+    if let Err(err) = result {
+        fs::remove_dir_all(target).ok();
+        return Err(err);
+    }
+
+    extractor.files.sort();
+    Ok(extractor.files)
+}
+
+// symlinks are packed as links, whatever they point to stays out of the archive
+pub fn pack(source: &Path, archive: &Path) -> Result<(), String> {
+    let file = File::create(archive).map_err(|err| format!("{}: {}", archive.display(), err))?;
+
+    let name = archive.to_string_lossy();
+    let result = if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+        let mut builder = tar::Builder::new(GzEncoder::new(file, Compression::default()));
+        builder.follow_symlinks(false);
+        builder
+            .append_dir_all(".", source)
+            .and_then(|_| builder.into_inner())
+            .and_then(|encoder| encoder.finish().map(|_| ()))
+    } else {
+        let mut builder = tar::Builder::new(file);
+        builder.follow_symlinks(false);
+        builder
+            .append_dir_all(".", source)
+            .and_then(|_| builder.finish())
+    };
+
+    result.map_err(|err| format!("{}: {}", archive.display(), err))
+}
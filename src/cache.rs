@@ -0,0 +1,179 @@
+use std::{
+    fs::{self, File},
+    io,
+    os::unix::ffi::OsStrExt,
+    path::{Path, PathBuf},
+    process,
+    time::SystemTime,
+};
+
+use sha2::{Digest, Sha256};
+
+const STATUS_FILE: &str = "status";
+const BINARY_FILE: &str = "binary";
+const DIAGNOSTICS_FILE: &str = "diagnostics";
+
+pub struct CacheEntry {
+    pub status: i32,
+    pub binary: Option<PathBuf>,
+    pub diagnostics: PathBuf,
+}
+
+pub struct CompileCache {
+    dir: PathBuf,
+    max_size: u64,
+}
+
+pub fn default_cache_dir() -> PathBuf {
+    match std::env::var_os("XDG_CACHE_HOME") {
+        Some(dir) => PathBuf::from(dir).join("sandbox"),
+        None => match std::env::var_os("HOME") {
+            Some(home) => PathBuf::from(home).join(".cache").join("sandbox"),
+            None => std::env::temp_dir().join("sandbox-cache"),
+        },
+    }
+}
+
+pub fn hash_file(path: &Path) -> io::Result<Vec<u8>> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(hasher.finalize().to_vec())
+}
+
+// every file under path along with its relative path, in a stable order
+pub fn hash_dir(path: &Path) -> io::Result<Vec<u8>> {
+    let mut hasher = Sha256::new();
+    hash_entries(path, Path::new(""), &mut hasher)?;
+    Ok(hasher.finalize().to_vec())
+}
+
+fn hash_entries(root: &Path, relative: &Path, hasher: &mut Sha256) -> io::Result<()> {
+    let mut entries = fs::read_dir(root.join(relative))?.collect::<io::Result<Vec<_>>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
+
+    for entry in entries {
+        let relative = relative.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            hash_entries(root, &relative, hasher)?;
+            continue;
+        }
+        // a link to a directory fails here, which leaves the compile uncached
+        let content = hash_file(&root.join(&relative))?;
+        hasher.update(relative.as_os_str().as_bytes());
+        hasher.update([0]);
+        hasher.update(content);
+    }
+    Ok(())
+}
+
+fn dir_size(path: &Path) -> u64 {
+    match fs::read_dir(path) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| entry.metadata().ok())
+            .map(|metadata| metadata.len())
+            .sum(),
+        Err(_) => 0,
+    }
+}
+
+impl CompileCache {
+    pub fn new(dir: PathBuf, max_size: u64) -> Self {
+        CompileCache { dir, max_size }
+    }
+
+    pub fn key(parts: &[&[u8]]) -> String {
+        let mut hasher = Sha256::new();
+        for part in parts {
+            hasher.update((part.len() as u64).to_le_bytes());
+            hasher.update(part);
+        }
+
+        hasher
+            .finalize()
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect()
+    }
+
+    pub fn lookup(&self, key: &str) -> Option<CacheEntry> {
+        let entry_dir = self.dir.join(key);
+        let status: i32 = fs::read_to_string(entry_dir.join(STATUS_FILE))
+            .ok()?
+            .trim()
+            .parse()
+            .ok()?;
+
+        // the status file's mtime doubles as the last access time for eviction
+        File::options()
+            .write(true)
+            .open(entry_dir.join(STATUS_FILE))
+            .and_then(|file| file.set_modified(SystemTime::now()))
+            .ok()?;
+
+        let binary = entry_dir.join(BINARY_FILE);
+        Some(CacheEntry {
+            status,
+            binary: if binary.exists() { Some(binary) } else { None },
+            diagnostics: entry_dir.join(DIAGNOSTICS_FILE),
+        })
+    }
+
+    pub fn store(
+        &self,
+        key: &str,
+        status: i32,
+        binary: Option<&Path>,
+        diagnostics: &Path,
+    ) -> io::Result<()> {
+        let staging_dir = self.dir.join(format!(".staging-{}-{}", key, process::id()));
+        fs::create_dir_all(&staging_dir)?;
+
+        if let Some(binary) = binary {
+            fs::copy(binary, staging_dir.join(BINARY_FILE))?;
+        }
+        fs::copy(diagnostics, staging_dir.join(DIAGNOSTICS_FILE))?;
+        // written last so that a partially stored entry is never looked up
+        fs::write(staging_dir.join(STATUS_FILE), status.to_string())?;
+
+        let entry_dir = self.dir.join(key);
+        if fs::rename(&staging_dir, &entry_dir).is_err() {
+            // another judge stored the same key first
+            fs::remove_dir_all(&staging_dir)?;
+        }
+
+        self.evict()
+    }
+
+    pub fn diagnostics_path(&self) -> io::Result<PathBuf> {
+        fs::create_dir_all(&self.dir)?;
+        Ok(self.dir.join(format!(".diagnostics-{}", process::id())))
+    }
+
+    fn evict(&self) -> io::Result<()> {
+        let mut entries: Vec<(SystemTime, u64, PathBuf)> = fs::read_dir(&self.dir)?
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| !entry.file_name().to_string_lossy().starts_with('.'))
+            .filter_map(|entry| {
+                let path = entry.path();
+                let accessed = fs::metadata(path.join(STATUS_FILE))
+                    .and_then(|metadata| metadata.modified())
+                    .ok()?;
+                Some((accessed, dir_size(&path), path))
+            })
+            .collect();
+
+        let mut total: u64 = entries.iter().map(|(_, size, _)| size).sum();
+        entries.sort();
+
+        for (_, size, path) in entries {
+            if total <= self.max_size {
+                break;
+            }
+            fs::remove_dir_all(&path)?;
+            total -= size;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,123 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+    thread,
+    time::Duration,
+};
+
+use nix::{
+    sys::signal::{kill, Signal},
+    unistd::Pid,
+};
+
+// sandboxes of the same process each get their own
+static COUNT: AtomicUsize = AtomicUsize::new(0);
+
+// a pids cgroup of the sandbox's own: unlike RLIMIT_NPROC it also holds for root and only counts
+// what the program forks, not everything else its uid runs
+pub struct PidsCgroup {
+    path: PathBuf,
+}
+
+impl PidsCgroup {
+    pub fn new(max: u64) -> Result<Self, String> {
+        let parent = pids_hierarchy().ok_or("no pids cgroup controller is mounted")?;
+        // controllers of a v2 cgroup only reach its children once enabled, v1 has no such file
+        fs::write(parent.join("cgroup.subtree_control"), "+pids").ok();
+
+        let name = format!(
+            "sandbox-{}-{}",
+            std::process::id(),
+            COUNT.fetch_add(1, Ordering::Relaxed)
+        );
+        let path = parent.join(name);
+        fs::create_dir(&path).map_err(|err| format!("{}: {}", path.display(), err))?;
+
+        let cgroup = PidsCgroup { path };
+        fs::write(cgroup.path.join("pids.max"), max.to_string())
+            .map_err(|err| format!("{}: {}", cgroup.path.display(), err))?;
+        Ok(cgroup)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+// moves the calling process, and so everything it forks from now on, into the cgroup
+pub fn join(path: &Path) {
+    if let Err(err) = fs::write(path.join("cgroup.procs"), "0") {
+        panic!("joining {} failed: {}", path.display(), err);
+    }
+}
+
+// whatever the program left behind is killed so that the cgroup can be removed
+impl Drop for PidsCgroup {
+    fn drop(&mut self) {
+        for _ in 0..100 {
+            if fs::remove_dir(&self.path).is_ok() {
+                return;
+            }
+            if fs::write(self.path.join("cgroup.kill"), "1").is_err() {
+                let procs = fs::read_to_string(self.path.join("cgroup.procs")).unwrap_or_default();
+                for pid in procs.lines().filter_map(|pid| pid.parse().ok()) {
+                    kill(Pid::from_raw(pid), Signal::SIGKILL).ok();
+                }
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+}
+
+// our own cgroup in the v1 pids hierarchy, or in the v2 one when it has the pids controller
+fn pids_hierarchy() -> Option<PathBuf> {
+    let cgroups = fs::read_to_string("/proc/self/cgroup").ok()?;
+    let mountinfo = fs::read_to_string("/proc/self/mountinfo").ok()?;
+
+    let mut v2 = None;
+    for line in cgroups.lines() {
+        let mut fields = line.splitn(3, ':');
+        let (_, controllers, path) = (fields.next()?, fields.next()?, fields.next()?);
+        if controllers
+            .split(',')
+            .any(|controller| controller == "pids")
+        {
+            return mount_point(&mountinfo, "cgroup", Some("pids"), path);
+        }
+        if controllers.is_empty() {
+            v2 = Some(path);
+        }
+    }
+
+    let path = mount_point(&mountinfo, "cgroup2", None, v2?)?;
+    let controllers = fs::read_to_string(path.join("cgroup.controllers")).ok()?;
+    controllers
+        .split_whitespace()
+        .any(|controller| controller == "pids")
+        .then_some(path)
+}
+
+// where the cgroup at `path` of a hierarchy shows up, given the mounts of /proc/self/mountinfo
+fn mount_point(
+    mountinfo: &str,
+    fs_type: &str,
+    option: Option<&str>,
+    path: &str,
+) -> Option<PathBuf> {
+    mountinfo.lines().find_map(|line| {
+        // <id> <parent> <dev> <root> <mount point> ... - <type> <source> <super options>
+        let (mount, filesystem) = line.split_once(" - ")?;
+        let mount: Vec<&str> = mount.split(' ').collect();
+        let filesystem: Vec<&str> = filesystem.split(' ').collect();
+        if filesystem.first() != Some(&fs_type) {
+            return None;
+        }
+        if let Some(option) = option {
+            filesystem.get(2)?.split(',').find(|o| *o == option)?;
+        }
+
+        let relative = Path::new(path).strip_prefix(mount.get(3)?).ok()?;
+        Some(Path::new(mount.get(4)?).join(relative))
+    })
+}
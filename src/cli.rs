@@ -16,7 +16,7 @@ fn add_build_command(app: Command) -> Command {
                 Arg::new("input")
                     .short('i')
                     .long("input")
-                    .help("input file")
+                    .help("input source file or .tar, .tar.gz, .zip archive")
                     .takes_value(true)
                     .required(true),
             )
@@ -34,6 +34,145 @@ fn add_build_command(app: Command) -> Command {
                     .help("compile time limit in second")
                     .takes_value(true)
                     .required(false),
+            )
+            .arg(
+                Arg::new("workdir")
+                    .long("workdir")
+                    .help("working directory")
+                    .takes_value(true)
+                    .required(false),
+            )
+            .arg(
+                Arg::new("rootdir")
+                    .long("rootdir")
+                    .help("toolchain root directory")
+                    .takes_value(true)
+                    .required(false),
+            )
+            .arg(
+                Arg::new("mount")
+                    .long("mount")
+                    .help("mount laid out with pivot_root: ro:<src>[:<dst>], rw:<src>[:<dst>], tmpfs:<dst>[:<bytes>], dev or proc")
+                    .takes_value(true)
+                    .multiple_occurrences(true)
+                    .required(false),
+            )
+            .arg(
+                Arg::new("mount_spec")
+                    .long("mount-spec")
+                    .help("TOML/JSON file of mounts laid out with pivot_root")
+                    .takes_value(true)
+                    .required(false),
+            )
+            .arg(
+                Arg::new("landlock")
+                    .long("landlock")
+                    .help("restrict file access with Landlock to reading the root or system paths and writing the workdir and writable mounts")
+                    .takes_value(false)
+                    .required(false),
+            )
+            .arg(
+                Arg::new("no_cache")
+                    .long("no-cache")
+                    .help("always compile, bypassing the compile cache")
+                    .takes_value(false)
+                    .required(false),
+            )
+            .arg(
+                Arg::new("cache_dir")
+                    .long("cache-dir")
+                    .help("compile cache directory")
+                    .takes_value(true)
+                    .required(false),
+            )
+            .arg(
+                Arg::new("cache_size")
+                    .long("cache-size")
+                    .help("compile cache size limit in bytes")
+                    .takes_value(true)
+                    .required(false),
+            )
+            .arg(
+                Arg::new("flag")
+                    .long("flag")
+                    .help("extra compile flag allowed by the language")
+                    .takes_value(true)
+                    .allow_hyphen_values(true)
+                    .multiple_occurrences(true)
+                    .required(false),
+            )
+            .arg(
+                Arg::new("define")
+                    .short('D')
+                    .long("define")
+                    .help("preprocessor define allowed by the language")
+                    .takes_value(true)
+                    .multiple_occurrences(true)
+                    .required(false),
+            )
+            .arg(
+                Arg::new("source")
+                    .long("source")
+                    .help("additional source file")
+                    .takes_value(true)
+                    .multiple_occurrences(true)
+                    .required(false),
+            )
+            .arg(
+                Arg::new("grader")
+                    .long("grader")
+                    .help("grader directory compiled and linked with the submission")
+                    .takes_value(true)
+                    .required(false),
+            )
+            .arg(
+                Arg::new("grader_include")
+                    .long("grader-include")
+                    .help("directory of grader headers the submission may include")
+                    .takes_value(true)
+                    .required(false),
+            )
+            .arg(
+                Arg::new("extract_dir")
+                    .long("extract-dir")
+                    .help("fresh directory to extract an archive input into")
+                    .takes_value(true)
+                    .required(false),
+            )
+            .arg(
+                Arg::new("archive_entries")
+                    .long("archive-entries")
+                    .help("maximum number of entries in an archive input")
+                    .takes_value(true)
+                    .required(false),
+            )
+            .arg(
+                Arg::new("archive_size")
+                    .long("archive-size")
+                    .help("maximum extracted size of an archive input in bytes")
+                    .takes_value(true)
+                    .required(false),
+            )
+            .arg(
+                Arg::new("seccomp_profile")
+                    .long("seccomp-profile")
+                    .help("builtin syscall profile name or TOML/JSON profile file")
+                    .takes_value(true)
+                    .required(false),
+            )
+            .arg(
+                Arg::new("rootless")
+                    .long("rootless")
+                    .help("run in a user namespace instead of as root, the default for unprivileged users")
+                    .takes_value(false)
+                    .required(false),
+            )
+            .arg(
+                Arg::new("user")
+                    .long("user")
+                    .help("uid[:gid] to run as, without capabilities or supplementary groups")
+                    .takes_value(true)
+                    .required(false),
             ),
     )
 }
@@ -74,6 +213,14 @@ fn add_run_command(app: Command) -> Command {
                     .takes_value(true)
                     .required(false),
             )
+            .arg(
+                Arg::new("output_limit")
+                    .long("output-limit")
+                    .help("keep up to this many bytes of stdout in memory to judge and report, instead of a file")
+                    .takes_value(true)
+                    .conflicts_with("output")
+                    .required(false),
+            )
             .arg(
                 Arg::new("answer")
                     .short('a')
@@ -82,6 +229,22 @@ fn add_run_command(app: Command) -> Command {
                     .takes_value(true)
                     .required(false),
             )
+            .arg(
+                Arg::new("error")
+                    .short('e')
+                    .long("error")
+                    .help("file to write the program's stderr to")
+                    .takes_value(true)
+                    .required(false),
+            )
+            .arg(
+                Arg::new("capture_stderr")
+                    .long("capture-stderr")
+                    .help("keep up to this many bytes of stderr for the run report")
+                    .takes_value(true)
+                    .conflicts_with("error")
+                    .required(false),
+            )
             .arg(
                 Arg::new("memory_limit")
                     .long("memory")
@@ -117,6 +280,248 @@ fn add_run_command(app: Command) -> Command {
                     .help("root directory")
                     .takes_value(true)
                     .required(false),
+            )
+            .arg(
+                Arg::new("mount")
+                    .long("mount")
+                    .help("mount laid out with pivot_root: ro:<src>[:<dst>], rw:<src>[:<dst>], tmpfs:<dst>[:<bytes>], dev or proc")
+                    .takes_value(true)
+                    .multiple_occurrences(true)
+                    .required(false),
+            )
+            .arg(
+                Arg::new("mount_spec")
+                    .long("mount-spec")
+                    .help("TOML/JSON file of mounts laid out with pivot_root")
+                    .takes_value(true)
+                    .required(false),
+            )
+            .arg(
+                Arg::new("landlock")
+                    .long("landlock")
+                    .help("restrict file access with Landlock to reading the root or system paths and writing the workdir and writable mounts")
+                    .takes_value(false)
+                    .required(false),
+            )
+            .arg(
+                Arg::new("overlay")
+                    .long("overlay")
+                    .help("give the program a throwaway writable layer over the root directory")
+                    .takes_value(false)
+                    .required(false),
+            )
+            .arg(
+                Arg::new("overlay_size")
+                    .long("overlay-size")
+                    .help("size limit of the overlay's writable layer in bytes")
+                    .takes_value(true)
+                    .requires("overlay")
+                    .required(false),
+            )
+            .arg(
+                Arg::new("overlay_archive")
+                    .long("overlay-archive")
+                    .help(".tar or .tar.gz file to keep the overlay's writable layer in after the run")
+                    .takes_value(true)
+                    .requires("overlay")
+                    .required(false),
+            )
+            .arg(
+                Arg::new("seccomp_profile")
+                    .long("seccomp-profile")
+                    .help("builtin syscall profile name or TOML/JSON profile file")
+                    .takes_value(true)
+                    .required(false),
+            )
+            .arg(
+                Arg::new("seccomp_default")
+                    .long("seccomp-default")
+                    .help("action for system calls outside the profile, e.g. kill_process, errno:1, log")
+                    .takes_value(true)
+                    .required(false),
+            )
+            .arg(
+                Arg::new("mediate")
+                    .long("mediate")
+                    .help("openat/connect rule for the supervisor, e.g. allow:path:/tmp/data, log:port:8080")
+                    .takes_value(true)
+                    .multiple_occurrences(true)
+                    .required(false),
+            )
+            .arg(
+                Arg::new("mediate_default")
+                    .long("mediate-default")
+                    .help("supervisor action for requests no rule matches: allow, deny or log")
+                    .takes_value(true)
+                    .required(false),
+            )
+            .arg(
+                Arg::new("pass_fd")
+                    .long("pass-fd")
+                    .help("file descriptor the program inherits, all others above stderr are closed")
+                    .takes_value(true)
+                    .multiple_occurrences(true)
+                    .required(false),
+            )
+            .arg(
+                Arg::new("report")
+                    .long("report")
+                    .help("JSON file to write the run report to")
+                    .takes_value(true)
+                    .required(false),
+            )
+            .arg(
+                Arg::new("syscall_stats")
+                    .long("syscall-stats")
+                    .help("count the system calls made and add them to the report")
+                    .takes_value(false)
+                    .required(false),
+            )
+            .arg(
+                Arg::new("namespaces")
+                    .long("namespaces")
+                    .help("namespaces to isolate the program in: all or a list of user, pid, mount, net, ipc, uts, cgroup")
+                    .takes_value(true)
+                    .required(false),
+            )
+            .arg(
+                Arg::new("rootless")
+                    .long("rootless")
+                    .help("run in a user namespace instead of as root, the default for unprivileged users")
+                    .takes_value(false)
+                    .required(false),
+            )
+            .arg(
+                Arg::new("user")
+                    .long("user")
+                    .help("uid[:gid] to run as, without capabilities or supplementary groups")
+                    .takes_value(true)
+                    .required(false),
+            ),
+    )
+}
+
+fn add_profile_command(app: Command) -> Command {
+    app.subcommand(
+        Command::new("profile")
+            .about("manage syscall profiles")
+            .subcommand_required(true)
+            .subcommand(
+                Command::new("learn")
+                    .about("record the system calls of a program into a profile")
+                    .arg(
+                        Arg::new("file")
+                            .short('f')
+                            .long("file")
+                            .help("executable file")
+                            .takes_value(true)
+                            .required(true),
+                    )
+                    .arg(
+                        Arg::new("arg")
+                            .long("arg")
+                            .help("argument passed to the executable")
+                            .takes_value(true)
+                            .allow_hyphen_values(true)
+                            .multiple_occurrences(true)
+                            .required(false),
+                    )
+                    .arg(
+                        Arg::new("profile")
+                            .short('p')
+                            .long("profile")
+                            .help("profile file to write, TOML unless it ends with .json")
+                            .takes_value(true)
+                            .required(true),
+                    )
+                    .arg(
+                        Arg::new("input")
+                            .short('i')
+                            .long("input")
+                            .help("input file")
+                            .takes_value(true)
+                            .required(false),
+                    )
+                    .arg(
+                        Arg::new("output")
+                            .short('o')
+                            .long("output")
+                            .help("output file")
+                            .takes_value(true)
+                            .required(false),
+                    )
+                    .arg(
+                        Arg::new("memory_limit")
+                            .long("memory")
+                            .help("memory limit in bytes")
+                            .takes_value(true)
+                            .required(true),
+                    )
+                    .arg(
+                        Arg::new("time_limit")
+                            .long("time")
+                            .help("runtime limit in second")
+                            .takes_value(true)
+                            .required(true),
+                    )
+                    .arg(
+                        Arg::new("env")
+                            .long("env")
+                            .help("environment variables")
+                            .takes_value(true)
+                            .multiple_occurrences(true)
+                            .required(false),
+                    )
+                    .arg(
+                        Arg::new("workdir")
+                            .long("workdir")
+                            .help("working directory")
+                            .takes_value(true)
+                            .required(false),
+                    )
+                    .arg(
+                        Arg::new("rootdir")
+                            .long("rootdir")
+                            .help("root directory")
+                            .takes_value(true)
+                            .required(false),
+                    )
+                    .arg(
+                        Arg::new("mount")
+                            .long("mount")
+                            .help("mount laid out with pivot_root: ro:<src>[:<dst>], rw:<src>[:<dst>], tmpfs:<dst>[:<bytes>], dev or proc")
+                            .takes_value(true)
+                            .multiple_occurrences(true)
+                            .required(false),
+                    )
+                    .arg(
+                        Arg::new("mount_spec")
+                            .long("mount-spec")
+                            .help("TOML/JSON file of mounts laid out with pivot_root")
+                            .takes_value(true)
+                            .required(false),
+                    )
+                    .arg(
+                        Arg::new("landlock")
+                            .long("landlock")
+                            .help("restrict file access with Landlock to reading the root or system paths and writing the workdir and writable mounts")
+                            .takes_value(false)
+                            .required(false),
+                    )
+                    .arg(
+                        Arg::new("rootless")
+                            .long("rootless")
+                            .help("run in a user namespace instead of as root, the default for unprivileged users")
+                            .takes_value(false)
+                            .required(false),
+                    )
+                    .arg(
+                        Arg::new("user")
+                            .long("user")
+                            .help("uid[:gid] to run as, without capabilities or supplementary groups")
+                            .takes_value(true)
+                            .required(false),
+                    ),
             ),
     )
 }
@@ -124,5 +529,6 @@ fn add_run_command(app: Command) -> Command {
 pub fn init<'a>() -> Command<'a> {
     let app = Command::new("Sandbox").version("0.0.1");
     let app = add_build_command(app);
-    add_run_command(app)
+    let app = add_run_command(app);
+    add_profile_command(app)
 }
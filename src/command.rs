@@ -1,9 +1,21 @@
 use std::collections::HashMap;
 use std::vec::Vec;
+use std::{fs, io, path::Path};
+
+use nix::libc;
 
 use crate::{
-    executor::{execute, ExecuteOption, ResourceLimit},
-    process::Directory,
+    archive::{extract, is_archive, ExtractLimit},
+    cache::{hash_dir, hash_file, CompileCache},
+    executor::{execute, execute_report, execute_traced, ExecuteOption, ResourceLimit},
+    exit_code::ExitCode,
+    mount::ScratchDir,
+    namespace::{check_user_namespaces, Namespaces},
+    process::{Credentials, Directory},
+    report::RunReport,
+    seccomp::{arch_name, SyscallArg, SyscallArgCompare, SyscallFilter, SyscallFilterAction},
+    supervisor::SupervisorPolicy,
+    trace::TraceMode,
 };
 
 pub struct CompileOption {
@@ -11,6 +23,19 @@ pub struct CompileOption {
     pub input_path: String,
     pub output_path: String,
     pub time_limit: u64,
+    pub directory: Directory,
+    pub cache: Option<CompileCache>,
+    pub extra_flags: Vec<String>,
+    pub defines: Vec<String>,
+    pub extra_sources: Vec<String>,
+    pub grader_dir: Option<String>,
+    // the grader's public headers, the only part of it on the submission's include path
+    pub grader_include: Option<String>,
+    pub extract_dir: Option<String>,
+    pub archive_limit: ExtractLimit,
+    pub syscall_profile: Option<String>,
+    pub rootless: bool,
+    pub user: Option<Credentials>,
 }
 
 pub struct RunOption {
@@ -18,29 +43,68 @@ pub struct RunOption {
     pub file_path: String,
     pub input_path: Option<String>,
     pub output_path: Option<String>,
+    // captures stdout in memory up to this many bytes
+    pub output_limit: Option<u64>,
     pub answer_path: Option<String>,
+    pub error_path: Option<String>,
+    // captures stderr in memory up to this many bytes
+    pub error_limit: Option<u64>,
+    pub time_limit: u64,
+    pub memory_limit: u64,
+    pub envs: Vec<String>,
+    pub directory: Directory,
+    pub syscall_profile: Option<String>,
+    pub syscall_default: Option<SyscallFilterAction>,
+    pub supervisor: Option<SupervisorPolicy>,
+    pub syscall_stats: bool,
+    pub report_path: Option<String>,
+    pub namespaces: Option<Namespaces>,
+    pub rootless: bool,
+    pub user: Option<Credentials>,
+    pub pass_fds: Vec<i32>,
+}
+
+pub struct LearnOption {
+    pub file_path: String,
+    pub args: Vec<String>,
+    pub input_path: Option<String>,
+    pub output_path: Option<String>,
+    pub profile_path: String,
     pub time_limit: u64,
     pub memory_limit: u64,
     pub envs: Vec<String>,
     pub directory: Directory,
+    pub rootless: bool,
+    pub user: Option<Credentials>,
 }
 
 enum FlagToken {
-    INPUT,
-    OUTPUT,
-    BINARY,
+    Input,
+    Output,
+    Binary,
+    Sources,
+    Extra,
 }
 
 impl FlagToken {
     fn value(&self) -> &str {
         match *self {
-            FlagToken::INPUT => "<INPUT>",
-            FlagToken::OUTPUT => "<OUTPUT>",
-            FlagToken::BINARY => "<BINARY>",
+            FlagToken::Input => "<INPUT>",
+            FlagToken::Output => "<OUTPUT>",
+            FlagToken::Binary => "<BINARY>",
+            FlagToken::Sources => "<SOURCES>",
+            FlagToken::Extra => "<EXTRA>",
         }
     }
 }
 
+#[derive(Clone)]
+struct CompileAllowlist {
+    flags: Vec<&'static str>,
+    defines: Vec<&'static str>,
+    extensions: Vec<&'static str>,
+}
+
 fn get_compile_flags(language: &str) -> Option<(&str, Vec<&str>)> {
     let map: HashMap<&str, (&str, Vec<&str>)> = [
         (
@@ -49,12 +113,14 @@ fn get_compile_flags(language: &str) -> Option<(&str, Vec<&str>)> {
                 "/usr/bin/gcc",
                 vec![
                     "gcc",
-                    FlagToken::INPUT.value(),
+                    FlagToken::Input.value(),
+                    FlagToken::Sources.value(),
                     "-O2",
                     "-Wall",
                     "-lm",
+                    FlagToken::Extra.value(),
                     "-o",
-                    FlagToken::OUTPUT.value(),
+                    FlagToken::Output.value(),
                 ],
             ),
         ),
@@ -64,12 +130,14 @@ fn get_compile_flags(language: &str) -> Option<(&str, Vec<&str>)> {
                 "/usr/bin/g++",
                 vec![
                     "g++",
-                    FlagToken::INPUT.value(),
+                    FlagToken::Input.value(),
+                    FlagToken::Sources.value(),
                     "-O2",
                     "-Wall",
                     "-lm",
+                    FlagToken::Extra.value(),
                     "-o",
-                    FlagToken::OUTPUT.value(),
+                    FlagToken::Output.value(),
                 ],
             ),
         ),
@@ -81,10 +149,108 @@ fn get_compile_flags(language: &str) -> Option<(&str, Vec<&str>)> {
     map.get(language).cloned()
 }
 
+fn get_compile_allowlist(language: &str) -> Option<CompileAllowlist> {
+    let common_flags = vec!["-O0", "-O1", "-O2", "-O3", "-static", "-pthread", "-lm"];
+    let defines = vec!["ONLINE_JUDGE", "EVAL", "NDEBUG"];
+
+    let map: HashMap<&str, CompileAllowlist> = [
+        (
+            "c",
+            CompileAllowlist {
+                flags: [
+                    common_flags.clone(),
+                    vec![
+                        "-std=c99",
+                        "-std=c11",
+                        "-std=c17",
+                        "-std=gnu99",
+                        "-std=gnu11",
+                        "-std=gnu17",
+                    ],
+                ]
+                .concat(),
+                defines: defines.clone(),
+                extensions: vec!["c"],
+            },
+        ),
+        (
+            "cpp",
+            CompileAllowlist {
+                flags: [
+                    common_flags,
+                    vec![
+                        "-std=c++11",
+                        "-std=c++14",
+                        "-std=c++17",
+                        "-std=c++20",
+                        "-std=gnu++11",
+                        "-std=gnu++14",
+                        "-std=gnu++17",
+                        "-std=gnu++20",
+                    ],
+                ]
+                .concat(),
+                defines,
+                extensions: vec!["cpp", "cc", "cxx"],
+            },
+        ),
+    ]
+    .iter()
+    .cloned()
+    .collect();
+
+    map.get(language).cloned()
+}
+
+fn validate_define(define: &str, allowlist: &CompileAllowlist) -> Result<(), String> {
+    let (name, value) = match define.split_once('=') {
+        Some((name, value)) => (name, value),
+        None => (define, ""),
+    };
+
+    if !allowlist.defines.contains(&name) {
+        return Err(format!("define not allowed: {}", name));
+    }
+    if !value.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        return Err(format!("invalid define value: {}", define));
+    }
+
+    Ok(())
+}
+
+fn validate_source(source: &str, allowlist: &CompileAllowlist) -> Result<(), String> {
+    let extension = Path::new(source)
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .unwrap_or("");
+
+    if source.starts_with('-') || !allowlist.extensions.contains(&extension) {
+        return Err(format!("invalid source file: {}", source));
+    }
+
+    Ok(())
+}
+
+fn validate_extra_options(opt: &CompileOption, allowlist: &CompileAllowlist) -> Result<(), String> {
+    for flag in &opt.extra_flags {
+        if !allowlist.flags.contains(&flag.as_str()) {
+            return Err(format!("compile flag not allowed: {}", flag));
+        }
+    }
+    for define in &opt.defines {
+        validate_define(define, allowlist)?;
+    }
+    for source in &opt.extra_sources {
+        validate_source(source, allowlist)?;
+    }
+
+    Ok(())
+}
+
 fn get_run_flags(language: &str) -> Option<Vec<&str>> {
     let map: HashMap<&str, Vec<&str>> = [
-        ("c", vec![FlagToken::BINARY.value()]),
-        ("cpp", vec![FlagToken::BINARY.value()]),
+        ("c", vec![FlagToken::Binary.value()]),
+        ("cpp", vec![FlagToken::Binary.value()]),
     ]
     .iter()
     .cloned()
@@ -93,7 +259,129 @@ fn get_run_flags(language: &str) -> Option<Vec<&str>> {
     map.get(language).cloned()
 }
 
+fn get_syscall_profile(language: &str) -> Option<&str> {
+    let map: HashMap<&str, &str> = [("c", "native"), ("cpp", "native")]
+        .iter()
+        .cloned()
+        .collect();
+
+    map.get(language).cloned()
+}
+
+// rootless runs always get the namespaces that stand in for root
+fn get_namespaces(namespaces: Option<&Namespaces>, rootless: bool) -> Option<Namespaces> {
+    match rootless {
+        true => Some(namespaces.cloned().unwrap_or_default().rootless()),
+        false => namespaces.cloned(),
+    }
+}
+
+fn check_rootless(rootless: bool, user: Option<&Credentials>) -> Result<(), i32> {
+    if !rootless {
+        return Ok(());
+    }
+    // the user namespace only maps its root
+    if user.is_some_and(|user| user.uid != 0 || user.gid != 0) {
+        eprintln!("running as another user needs root, rootless mode has no other users");
+        return Err(ExitCode::Unknown as i32);
+    }
+    check_user_namespaces().map_err(|err| {
+        eprintln!("{}", err);
+        ExitCode::Unknown as i32
+    })
+}
+
+// every system call name is resolved here so a bad profile fails before the child forks
+fn load_syscall_filter(profile: &str) -> SyscallFilter {
+    let filter = match SyscallFilter::resolve(profile) {
+        Ok(filter) => filter,
+        Err(err) => panic!("invalid syscall profile {}: {}", profile, err),
+    };
+    if let Err(err) = filter.validate() {
+        panic!("invalid syscall profile {}: {}", profile, err);
+    }
+
+    filter
+}
+
 pub fn compile(opt: CompileOption) -> i32 {
+    if let Err(status) = check_rootless(opt.rootless, opt.user.as_ref()) {
+        return status;
+    }
+    if !is_archive(&opt.input_path) {
+        return compile_sources(&opt, std::slice::from_ref(&opt.input_path), None);
+    }
+
+    let allowlist = match get_compile_allowlist(&opt.language) {
+        Some(allowlist) => allowlist,
+        None => panic!("unsupported language: {}", opt.language),
+    };
+
+    // by default a fresh directory beside the output, where the compiler sees it as well
+    let mut scratch_dir = None;
+    let extract_dir = match &opt.extract_dir {
+        Some(extract_dir) => extract_dir.clone(),
+        None => {
+            let parent = match Path::new(&opt.output_path).parent() {
+                Some(parent) if parent != Path::new("") => parent.to_string_lossy().into_owned(),
+                _ => ".".to_string(),
+            };
+            let scratch = match ScratchDir::new_in(&opt.directory.host_path(&parent)) {
+                Ok(scratch) => scratch,
+                Err(err) => {
+                    eprintln!(
+                        "creating a directory beside {} failed: {}",
+                        opt.output_path, err
+                    );
+                    return ExitCode::Unknown as i32;
+                }
+            };
+            let name = scratch.path().file_name().unwrap().to_string_lossy();
+            let extract_dir = format!("{}/{}/src", parent.trim_end_matches('/'), name);
+            scratch_dir = Some(scratch);
+            extract_dir
+        }
+    };
+    // extraction needs a fresh directory, so one left from another build is not reused
+    if opt
+        .directory
+        .host_path(&extract_dir)
+        .symlink_metadata()
+        .is_ok()
+    {
+        eprintln!("extract directory {} already exists", extract_dir);
+        return ExitCode::Unknown as i32;
+    }
+    let files = match extract(
+        &opt.directory.host_path(&opt.input_path),
+        &opt.directory.host_path(&extract_dir),
+        &opt.archive_limit,
+    ) {
+        Ok(files) => files,
+        Err(err) => panic!("invalid archive {}: {}", opt.input_path, err),
+    };
+
+    let inputs: Vec<String> = files
+        .iter()
+        .filter_map(|file| file.to_str())
+        .filter(|file| validate_source(file, &allowlist).is_ok())
+        .map(|file| format!("{}/{}", extract_dir.trim_end_matches('/'), file))
+        .collect();
+
+    let status = match inputs.is_empty() {
+        true => {
+            eprintln!("no {} source files in {}", opt.language, opt.input_path);
+            ExitCode::Unknown as i32
+        }
+        false => compile_sources(&opt, &inputs, Some(&extract_dir)),
+    };
+    fs::remove_dir_all(opt.directory.host_path(&extract_dir)).ok();
+    drop(scratch_dir);
+
+    status
+}
+
+fn compile_sources(opt: &CompileOption, inputs: &[String], project_dir: Option<&str>) -> i32 {
     let compiler: &str;
     let compile_args: Vec<&str>;
     match get_compile_flags(&opt.language) {
@@ -106,19 +394,229 @@ pub fn compile(opt: CompileOption) -> i32 {
         }
     }
 
-    let compile_args: Vec<&str> = compile_args
+    let allowlist = get_compile_allowlist(&opt.language).unwrap();
+    if let Err(err) = validate_extra_options(opt, &allowlist) {
+        panic!("invalid compile option for {}: {}", opt.language, err);
+    }
+
+    let grader_sources = match &opt.grader_dir {
+        Some(grader_dir) => get_grader_sources(&opt.directory, grader_dir, &allowlist),
+        None => vec![],
+    };
+    let grader_objects: Vec<String> = (0..grader_sources.len())
+        .map(|i| format!("{}.grader{}.o", opt.output_path, i))
+        .collect();
+
+    let mut defines: Vec<String> = opt.defines.iter().map(|d| format!("-D{}", d)).collect();
+    if let Some(grader_include) = &opt.grader_include {
+        defines.push(format!("-I{}", grader_include));
+    }
+    if let Some(project_dir) = project_dir {
+        defines.push(format!("-I{}", project_dir));
+    }
+    let extra_args: Vec<&str> = defines
+        .iter()
+        .chain(opt.extra_flags.iter())
+        .map(String::as_str)
+        .collect();
+
+    let key = match &opt.cache {
+        Some(_) => compile_cache_key(opt, compiler, &compile_args, &extra_args, &grader_sources),
+        None => None,
+    };
+
+    // built with the same flags as the submission, only not linked
+    let grader: Vec<(&str, &str, Vec<&str>)> = grader_sources
+        .iter()
+        .zip(grader_objects.iter())
+        .map(|(source, object)| {
+            let mut args = expand_compile_args(&compile_args, &[source], &[], &extra_args, object);
+            args.insert(1, "-c");
+            (source.as_str(), object.as_str(), args)
+        })
+        .collect();
+
+    let inputs: Vec<&str> = inputs.iter().map(String::as_str).collect();
+    // the grader is linked in after the submission, so a second main fails to link
+    let sources: Vec<&str> = opt
+        .extra_sources
+        .iter()
+        .chain(grader_objects.iter())
+        .map(String::as_str)
+        .collect();
+    let compile_args = expand_compile_args(
+        &compile_args,
+        &inputs,
+        &sources,
+        &extra_args,
+        &opt.output_path,
+    );
+
+    let (cache, key) = match (&opt.cache, key) {
+        (Some(cache), Some(key)) => (cache, key),
+        _ => return build(compiler, compile_args, &grader, opt, None),
+    };
+
+    if let Some(entry) = cache.lookup(&key) {
+        println!("compile cache hit: {}", key);
+        if let Some(binary) = &entry.binary {
+            fs::copy(binary, opt.directory.host_path(&opt.output_path)).unwrap();
+        }
+        print_diagnostics(&entry.diagnostics);
+        return entry.status;
+    }
+
+    let diagnostics = cache.diagnostics_path().unwrap();
+    let status = build(
+        compiler,
+        compile_args,
+        &grader,
+        opt,
+        Some(diagnostics.to_string_lossy().to_string()),
+    );
+    print_diagnostics(&diagnostics);
+
+    // a compiler killed by a limit may succeed on a less loaded machine
+    if libc::WIFEXITED(status) {
+        let binary = opt.directory.host_path(&opt.output_path);
+        let binary = match status == 0 && binary.exists() {
+            true => Some(binary.as_path()),
+            false => None,
+        };
+        if let Err(err) = cache.store(&key, status, binary, &diagnostics) {
+            eprintln!("failed to store compile cache: {}", err);
+        }
+    }
+    fs::remove_file(&diagnostics).ok();
+
+    status
+}
+
+fn compile_cache_key(
+    opt: &CompileOption,
+    compiler: &str,
+    compile_args: &[&str],
+    extra_args: &[&str],
+    grader_sources: &[String],
+) -> Option<String> {
+    let mut sources = hash_file(&opt.directory.host_path(&opt.input_path)).ok()?;
+    for source in opt.extra_sources.iter().chain(grader_sources.iter()) {
+        sources.extend(hash_file(&opt.directory.host_path(source)).ok()?);
+    }
+    // headers come from anywhere on the include path, and the grader bundle may hold more
+    let include_dirs = extra_args.iter().filter_map(|arg| arg.strip_prefix("-I"));
+    for dir in include_dirs.chain(opt.grader_dir.as_deref()) {
+        sources.extend(hash_dir(&opt.directory.host_path(dir)).ok()?);
+    }
+    let toolchain = hash_file(&opt.directory.host_path(compiler)).ok()?;
+    let config = format!(
+        "{}\0{}\0{}",
+        compiler,
+        compile_args.join("\0"),
+        extra_args.join("\0")
+    );
+
+    Some(CompileCache::key(&[
+        &sources,
+        config.as_bytes(),
+        &toolchain,
+    ]))
+}
+
+fn get_grader_sources(
+    directory: &Directory,
+    grader_dir: &str,
+    allowlist: &CompileAllowlist,
+) -> Vec<String> {
+    let entries = match fs::read_dir(directory.host_path(grader_dir)) {
+        Ok(entries) => entries,
+        Err(err) => panic!("invalid grader directory {}: {}", grader_dir, err),
+    };
+
+    let mut sources: Vec<String> = entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.path().is_file())
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .filter(|name| validate_source(name, allowlist).is_ok())
+        .map(|name| format!("{}/{}", grader_dir.trim_end_matches('/'), name))
+        .collect();
+    sources.sort();
+
+    sources
+}
+
+// the language's compile command with its tokens filled in
+fn expand_compile_args<'a>(
+    template: &[&'a str],
+    inputs: &[&'a str],
+    sources: &[&'a str],
+    extra_args: &[&'a str],
+    output: &'a str,
+) -> Vec<&'a str> {
+    template
         .iter()
-        .map(|arg| {
-            if *arg == FlagToken::INPUT.value() {
-                return opt.input_path.as_str();
-            } else if *arg == FlagToken::OUTPUT.value() {
-                return opt.output_path.as_str();
+        .flat_map(|arg| {
+            if *arg == FlagToken::Input.value() {
+                return inputs.to_vec();
+            } else if *arg == FlagToken::Output.value() {
+                return vec![output];
+            } else if *arg == FlagToken::Sources.value() {
+                return sources.to_vec();
+            } else if *arg == FlagToken::Extra.value() {
+                return extra_args.to_vec();
             }
 
-            *arg
+            vec![*arg]
         })
-        .collect();
+        .collect()
+}
+
+fn build(
+    compiler: &str,
+    compile_args: Vec<&str>,
+    grader: &[(&str, &str, Vec<&str>)],
+    opt: &CompileOption,
+    error_path: Option<String>,
+) -> i32 {
+    let mut status = 0;
+
+    // grader diagnostics are discarded so the grader source is never shown to contestants
+    for (source, _, grader_args) in grader {
+        status = execute_compile(
+            compiler,
+            grader_args.clone(),
+            opt,
+            Some("/dev/null".to_string()),
+        );
+        if status != 0 {
+            eprintln!("grader compilation failed: {}", source);
+            break;
+        }
+    }
+
+    if status == 0 {
+        status = execute_compile(compiler, compile_args, opt, error_path);
+    }
+
+    for (_, object, _) in grader {
+        fs::remove_file(opt.directory.host_path(object)).ok();
+    }
 
+    status
+}
+
+fn print_diagnostics(path: &Path) {
+    if let Ok(mut file) = fs::File::open(path) {
+        io::copy(&mut file, &mut io::stderr()).ok();
+    }
+}
+
+fn execute_compile(
+    compiler: &str,
+    compile_args: Vec<&str>,
+    opt: &CompileOption,
+    error_path: Option<String>,
+) -> i32 {
     execute(
         compiler,
         compile_args,
@@ -127,17 +625,48 @@ pub fn compile(opt: CompileOption) -> i32 {
             limits: Some(ResourceLimit {
                 time: opt.time_limit,
                 memory: 268435456, // 256mb
+                processes: Some(64),
+                output: Some(67108864), // 64mb
             }),
             input_path: None,
+            input: None,
             output_path: None,
+            output_limit: None,
+            error_path,
+            error_limit: None,
             answer_path: None,
-            directory: None,
-            use_syscall: false,
+            answer: None,
+            directory: Some(opt.directory.clone()),
+            syscall_filter: Some(load_syscall_filter(
+                opt.syscall_profile.as_deref().unwrap_or("compile"),
+            )),
+            trace: None,
+            supervisor: None,
+            syscall_stats: false,
+            report_path: None,
+            // signals of the compiler can only reach its own processes
+            namespaces: get_namespaces(
+                Some(&Namespaces {
+                    pid: true,
+                    mount: true,
+                    ..Namespaces::default()
+                }),
+                opt.rootless,
+            ),
+            user: opt.user,
+            pass_fds: vec![],
         },
     )
 }
 
-pub fn run(opt: RunOption) -> i32 {
+// the report of the run, or the exit status when the sandbox could not run it
+pub fn run(opt: RunOption) -> Result<RunReport, i32> {
+    check_rootless(opt.rootless, opt.user.as_ref())?;
+    // the supervisor opens files for the program, past the Landlock rules it is held to
+    if opt.supervisor.is_some() && opt.directory.landlock {
+        eprintln!("mediation cannot be combined with landlock");
+        return Err(ExitCode::Unknown as i32);
+    }
     let args = match get_run_flags(&opt.language) {
         Some(args) => args,
         None => {
@@ -148,7 +677,7 @@ pub fn run(opt: RunOption) -> i32 {
     let args: Vec<&str> = args
         .iter()
         .map(|arg| {
-            if *arg == FlagToken::BINARY.value() {
+            if *arg == FlagToken::Binary.value() {
                 return opt.file_path.as_str();
             }
 
@@ -156,21 +685,119 @@ pub fn run(opt: RunOption) -> i32 {
         })
         .collect();
 
-    println!("{:?}", args);
+    let profile = match &opt.syscall_profile {
+        Some(profile) => profile.as_str(),
+        None => match get_syscall_profile(&opt.language) {
+            Some(profile) => profile,
+            None => panic!("no syscall profile for language: {}", opt.language),
+        },
+    };
+    let mut syscall_filter = load_syscall_filter(profile);
+    if let Some(action) = &opt.syscall_default {
+        syscall_filter.default_action(action.clone());
+    }
+
     let rlimit = ResourceLimit {
         time: opt.time_limit,
         memory: opt.memory_limit,
+        processes: None,
+        output: None,
     };
 
     let option = ExecuteOption {
         envs: Some(opt.envs.clone()),
         limits: Some(rlimit),
         input_path: opt.input_path.clone(),
+        input: None,
         output_path: opt.output_path.clone(),
+        output_limit: opt.output_limit,
+        error_path: opt.error_path.clone(),
+        error_limit: opt.error_limit,
         answer_path: opt.answer_path.clone(),
+        answer: None,
         directory: Some(opt.directory.clone()),
-        use_syscall: true,
+        syscall_filter: Some(syscall_filter),
+        trace: Some(TraceMode::Violations),
+        supervisor: opt.supervisor.clone(),
+        syscall_stats: opt.syscall_stats,
+        report_path: opt.report_path.clone(),
+        namespaces: get_namespaces(opt.namespaces.as_ref(), opt.rootless),
+        user: opt.user,
+        pass_fds: opt.pass_fds.clone(),
     };
 
-    execute(&opt.file_path, args, option)
+    execute_report(&opt.file_path, args, option).ok_or(ExitCode::Unknown as i32)
+}
+
+pub fn learn(opt: LearnOption) -> i32 {
+    if let Err(status) = check_rootless(opt.rootless, opt.user.as_ref()) {
+        return status;
+    }
+    let args: Vec<&str> = std::iter::once(opt.file_path.as_str())
+        .chain(opt.args.iter().map(String::as_str))
+        .collect();
+
+    let mut filter = SyscallFilter::new(SyscallFilterAction::Trace(0));
+    filter.bad_arch_action = SyscallFilterAction::Trace(0);
+
+    let option = ExecuteOption {
+        envs: Some(opt.envs.clone()),
+        limits: Some(ResourceLimit {
+            time: opt.time_limit,
+            memory: opt.memory_limit,
+            processes: None,
+            output: None,
+        }),
+        input_path: opt.input_path.clone(),
+        input: None,
+        output_path: opt.output_path.clone(),
+        output_limit: None,
+        error_path: None,
+        error_limit: None,
+        answer_path: None,
+        answer: None,
+        directory: Some(opt.directory.clone()),
+        // every system call stops at the tracer, which records it and lets it run
+        syscall_filter: Some(filter),
+        trace: Some(TraceMode::Learn),
+        supervisor: None,
+        syscall_stats: false,
+        report_path: None,
+        namespaces: get_namespaces(None, opt.rootless),
+        user: opt.user,
+        pass_fds: vec![],
+    };
+
+    let (status, tracer) = execute_traced(&opt.file_path, args, option);
+    let tracer = match tracer {
+        Some(tracer) => tracer,
+        None => return status,
+    };
+
+    let mut profile = SyscallFilter::new(SyscallFilterAction::KillProcess);
+    profile.architectures = tracer
+        .architectures
+        .iter()
+        .filter_map(|arch| arch_name(*arch))
+        .map(str::to_string)
+        .collect();
+    for (name, arg) in &tracer.observed {
+        let args = match arg {
+            Some((index, value)) => vec![SyscallArg::new(*index, SyscallArgCompare::Equal(*value))],
+            None => vec![],
+        };
+        profile.add_with_args(name, SyscallFilterAction::Allow, args);
+    }
+
+    if let Err(err) = profile.save(Path::new(&opt.profile_path)) {
+        eprintln!("{}", err);
+        return ExitCode::Unknown as i32;
+    }
+    println!(
+        "learned {} system call rules into {}",
+        profile.rules.len(),
+        opt.profile_path
+    );
+
+    status
 }
@@ -1,13 +1,29 @@
-use std::time::Duration;
+use std::{
+    panic::{self, AssertUnwindSafe},
+    time::Duration,
+};
 
-use nix::libc::{getrusage, usleep, waitpid, RUSAGE_CHILDREN, WNOHANG};
+use nix::libc::{self, getrusage, usleep, waitpid, __WALL, RUSAGE_CHILDREN, WIFSTOPPED, WNOHANG};
 use nix::unistd::fork;
 use nix::unistd::ForkResult::{Child, Parent};
 
+use crate::cgroup::PidsCgroup;
 use crate::exit_code::ExitCode;
-use crate::judge::{judge, JudgeOption};
-use crate::process::{Directory, Process, Resource};
+use crate::judge::{judge, JudgeOption, ResultKind};
+use crate::landlock;
+use crate::mount::ScratchDir;
+use crate::namespace::Namespaces;
+use crate::pipe::{Capture, Feed};
+use crate::process::{Credentials, Directory, Process, Resource};
+use crate::report::RunReport;
+use crate::seccomp::SyscallFilter;
+use crate::supervisor::{channel, Supervisor, SupervisorPolicy};
+use crate::trace::{TraceMode, Tracer};
 
+// what a panic exits with when it is not caught
+const PANIC_STATUS: i32 = 101;
+
+#[allow(dead_code)]
 #[derive(Debug)]
 pub struct ResourceUsage {
     pub user_time: Duration,
@@ -18,61 +34,255 @@ pub struct ResourceUsage {
 pub struct ResourceLimit {
     pub memory: u64,
     pub time: u64,
+    pub processes: Option<u64>,
+    pub output: Option<u64>,
 }
 
+#[derive(Default)]
 pub struct ExecuteOption {
     pub envs: Option<Vec<String>>,
     pub limits: Option<ResourceLimit>,
     pub input_path: Option<String>,
+    // fed through a pipe instead of input_path
+    pub input: Option<Vec<u8>>,
     pub output_path: Option<String>,
+    // stdout is kept in memory up to this many bytes instead of going to output_path
+    pub output_limit: Option<u64>,
+    pub error_path: Option<String>,
+    // stderr is kept in memory up to this many bytes instead of going to error_path
+    pub error_limit: Option<u64>,
     pub answer_path: Option<String>,
+    pub answer: Option<Vec<u8>>,
     pub directory: Option<Directory>,
-    pub use_syscall: bool,
+    pub syscall_filter: Option<SyscallFilter>,
+    pub trace: Option<TraceMode>,
+    pub supervisor: Option<SupervisorPolicy>,
+    pub syscall_stats: bool,
+    pub report_path: Option<String>,
+    pub namespaces: Option<Namespaces>,
+    pub user: Option<Credentials>,
+    // descriptors of ours the program inherits, all others are closed
+    pub pass_fds: Vec<i32>,
+}
+
+// the fraction of a timeval is in microseconds
+fn duration(time: libc::timeval) -> Duration {
+    Duration::from_secs(time.tv_sec as u64) + Duration::from_micros(time.tv_usec as u64)
 }
 
 pub fn execute(binary: &str, args: Vec<&str>, option: ExecuteOption) -> i32 {
+    execute_traced(binary, args, option).0
+}
+
+pub fn execute_traced(
+    binary: &str,
+    args: Vec<&str>,
+    option: ExecuteOption,
+) -> (i32, Option<Tracer>) {
+    let (status, tracer, _) = execute_all(binary, args, option);
+    (status, tracer)
+}
+
+// with the captured output and the verdict in the report, None when the child could not fork
+pub fn execute_report(binary: &str, args: Vec<&str>, option: ExecuteOption) -> Option<RunReport> {
+    execute_all(binary, args, option).2
+}
+
+fn execute_all(
+    binary: &str,
+    args: Vec<&str>,
+    option: ExecuteOption,
+) -> (i32, Option<Tracer>, Option<RunReport>) {
+    let trace = match option.syscall_filter {
+        Some(_) => option.trace,
+        None => None,
+    };
+    let supervisor_channel = match (&option.syscall_filter, &option.supervisor) {
+        (Some(_), Some(_)) => Some(channel()),
+        _ => None,
+    };
+    let input_feed = option.input.clone().map(Feed::new);
+    let output_capture = option.output_limit.map(Capture::new);
+    let error_capture = option.error_limit.map(Capture::new);
+    // RLIMIT_NPROC is left to when no cgroup can be made, as it does not hold for root
+    let pids_cgroup = option
+        .limits
+        .as_ref()
+        .and_then(|limits| limits.processes)
+        .and_then(|processes| PidsCgroup::new(processes).ok());
+    let landlock_status = option
+        .directory
+        .as_ref()
+        .filter(|dir| dir.landlock)
+        .map(|_| landlock::status_pipe());
+    let scratch_dir = option
+        .directory
+        .as_ref()
+        .filter(|dir| dir.switches_root())
+        .map(|_| ScratchDir::new());
     let pid = unsafe { fork() };
 
     match pid {
         Ok(Child) => {
-            let mut process = Process::new(binary.to_string()).args(args);
+            // the child must never return into the caller's stack, not even by unwinding
+            let status = panic::catch_unwind(AssertUnwindSafe(|| {
+                let mut process = Process::new(binary.to_string()).args(args);
 
-            if option.envs.is_some() {
-                process = process.envs(option.envs.unwrap());
-            }
+                if let Some(envs) = option.envs {
+                    process = process.envs(envs);
+                }
 
-            if option.limits.is_some() {
-                let limits = option.limits.unwrap();
+                if let Some(limits) = option.limits {
+                    process = process
+                        .limit(Resource::AddressSpace, limits.memory)
+                        .limit(Resource::CPUTime, limits.time)
+                        .limit(Resource::CoreDump, 0);
 
-                process = process
-                    .limit(Resource::AddressSpace, limits.memory)
-                    .limit(Resource::CPUTime, limits.time)
-                    .limit(Resource::CoreDump, 0);
-            }
+                    match &pids_cgroup {
+                        Some(cgroup) => process = process.pids_cgroup(cgroup.path().to_path_buf()),
+                        None => {
+                            if let Some(processes) = limits.processes {
+                                process = process.limit(Resource::Processes, processes);
+                            }
+                        }
+                    }
+                    if let Some(output) = limits.output {
+                        process = process.limit(Resource::FileSize, output);
+                    }
+                }
 
-            if option.directory.is_some() {
-                process = process.dir(option.directory.unwrap());
-            }
+                if let Some(directory) = option.directory {
+                    process = process.dir(directory);
+                }
+                if let Some(scratch) = &scratch_dir {
+                    process = process.scratch_dir(scratch.path().to_path_buf());
+                }
 
-            if option.input_path.is_some() {
-                process = process.stdin(option.input_path.unwrap());
-            }
+                if let Some(namespaces) = option.namespaces {
+                    process = process.namespaces(namespaces);
+                }
 
-            if option.output_path.is_some() {
-                process = process.stdout(option.output_path.unwrap());
-            }
+                if let Some(user) = option.user {
+                    process = process.user(user);
+                }
+
+                for fd in &option.pass_fds {
+                    process = process.pass_fd(*fd);
+                }
+
+                if let Some(input_path) = option.input_path {
+                    process = process.stdin(input_path);
+                }
+
+                if let Some(output_path) = option.output_path {
+                    process = process.stdout(output_path);
+                }
+
+                if let Some(error_path) = option.error_path {
+                    process = process.stderr(error_path);
+                }
 
-            process = process.use_syscall_filter(option.use_syscall);
+                if let Some((mut feed, read_fd)) = input_feed {
+                    feed.close();
+                    process = process.stdin_pipe(read_fd);
+                }
+
+                if let Some((_, write_fd)) = output_capture {
+                    process = process.stdout_pipe(write_fd);
+                }
+
+                if let Some((_, write_fd)) = error_capture {
+                    process = process.stderr_pipe(write_fd);
+                }
+
+                if let Some((_, write_fd)) = landlock_status {
+                    process = process.landlock_status(write_fd);
+                }
+
+                if let Some(mut filter) = option.syscall_filter {
+                    if let (Some(policy), Some((_, child_end))) =
+                        (&option.supervisor, supervisor_channel)
+                    {
+                        process = process.supervise(policy.notify_filter(&filter), child_end);
+                        policy.mediate(&mut filter);
+                    }
+                    if trace == Some(TraceMode::Violations) {
+                        filter.trap_violations();
+                        if option.syscall_stats {
+                            filter.trace_allowed();
+                        }
+                    }
+                    if trace.is_some() {
+                        process = process.trace();
+                    }
+                    process = process.syscall_filter(filter);
+                }
 
-            process.run()
+                process.run()
+            }));
+            // the parent removes the cgroup, and the copies of everything else go with the process
+            unsafe { libc::_exit(status.unwrap_or(PANIC_STATUS)) }
         }
         Ok(Parent { child }) => {
             let mut status = 0;
             let mut usage = std::mem::MaybeUninit::uninit();
-            let mut resource_usage = None;
+            let resource_usage;
+            let mut tracer = trace.map(|mode| match option.syscall_stats {
+                true => Tracer::new(child, mode).count_syscalls(),
+                false => Tracer::new(child, mode),
+            });
+            let mut supervisor = match (option.supervisor.clone(), supervisor_channel) {
+                (Some(policy), Some((parent_end, child_end))) => {
+                    unsafe { libc::close(child_end) };
+                    Some(Supervisor::new(policy, parent_end))
+                }
+                _ => None,
+            };
+            let mut input_feed = input_feed.map(|(feed, read_fd)| {
+                unsafe { libc::close(read_fd) };
+                feed
+            });
+            let mut output_capture = output_capture.map(|(capture, write_fd)| {
+                Capture::close_write_end(write_fd);
+                capture
+            });
+            let mut error_capture = error_capture.map(|(capture, write_fd)| {
+                Capture::close_write_end(write_fd);
+                capture
+            });
+            let landlock_status = landlock_status.map(|(read_fd, write_fd)| {
+                unsafe { libc::close(write_fd) };
+                read_fd
+            });
             loop {
                 let child_pid = child.as_raw();
-                let wait_result = unsafe { waitpid(child_pid, &mut status, WNOHANG) };
+                let wait_result = match &mut tracer {
+                    // traced threads and processes report their stops to us as well, but only
+                    // they are waited for so that other children of the caller are left alone
+                    Some(tracer) => {
+                        let mut stopped = false;
+                        let mut wait_result = 0;
+                        for pid in tracer.tracees() {
+                            let mut pid_status = 0;
+                            let result =
+                                unsafe { waitpid(pid.as_raw(), &mut pid_status, WNOHANG | __WALL) };
+                            if result > 0 && WIFSTOPPED(pid_status) {
+                                tracer.handle_stop(pid, pid_status);
+                                stopped = true;
+                            } else if pid == child {
+                                status = pid_status;
+                                wait_result = result;
+                            } else if result != 0 {
+                                tracer.forget(pid);
+                            }
+                        }
+                        if stopped && wait_result == 0 {
+                            continue;
+                        }
+                        wait_result
+                    }
+                    None => unsafe { waitpid(child_pid, &mut status, WNOHANG) },
+                };
                 let rusage = unsafe {
                     match getrusage(RUSAGE_CHILDREN, usage.as_mut_ptr()) == 0 {
                         true => usage.assume_init(),
@@ -82,45 +292,73 @@ pub fn execute(binary: &str, args: Vec<&str>, option: ExecuteOption) -> i32 {
                     }
                 };
 
-                resource_usage = Some(ResourceUsage {
-                    user_time: Duration::new(
-                        rusage.ru_utime.tv_sec as u64,
-                        rusage.ru_utime.tv_usec as u32,
-                    ),
-                    cpu_time: Duration::new(
-                        rusage.ru_stime.tv_sec as u64,
-                        rusage.ru_stime.tv_usec as u32,
-                    ),
+                let usage = ResourceUsage {
+                    user_time: duration(rusage.ru_utime),
+                    cpu_time: duration(rusage.ru_stime),
                     memory: rusage.ru_maxrss as u64,
-                });
+                };
 
                 if wait_result != 0 {
-                    println!("{:?}", rusage);
-                    println!("{:?}", resource_usage);
-                    println!("exit code : {}", status);
+                    resource_usage = usage;
                     break;
                 }
-                unsafe {
-                    usleep(10000);
+                if let Some(feed) = &mut input_feed {
+                    feed.pump();
+                }
+                for capture in output_capture.iter_mut().chain(error_capture.iter_mut()) {
+                    capture.drain();
+                }
+                match &mut supervisor {
+                    Some(supervisor) => supervisor.serve(10),
+                    None => unsafe {
+                        usleep(10000);
+                    },
                 }
             }
 
-            let limits = option.limits.unwrap();
-            let judge_opt = JudgeOption {
-                output_path: option.output_path,
-                answer_path: option.answer_path,
-                time_limit: limits.time,
-                memory_limit: limits.memory,
-            };
-            let result = judge(status, resource_usage.unwrap(), judge_opt);
+            // along with anything the program left running
+            drop(pids_cgroup);
+            drop(scratch_dir);
+
+            let violation = tracer.as_ref().and_then(|tracer| tracer.violation.clone());
 
-            println!("{:?}", result);
+            let mut report = RunReport::new(status, &resource_usage);
+            report.syscalls = tracer.as_ref().and_then(|tracer| tracer.counts.clone());
+            if let Some(mut feed) = input_feed {
+                feed.close();
+            }
+            report.stdout = output_capture.map(Capture::finish);
+            report.stderr = error_capture.map(Capture::finish);
+            // what the child's restrict reported, nothing when it never got that far
+            report.landlock = landlock_status.and_then(landlock::receive_status);
+
+            if option.answer_path.is_some() || option.answer.is_some() {
+                let limits = option.limits.as_ref();
+                let judge_opt = JudgeOption {
+                    output_path: option.output_path,
+                    answer_path: option.answer_path,
+                    output: report.stdout.as_ref().map(|stdout| stdout.data.clone()),
+                    answer: option.answer,
+                    time_limit: limits.map(|limits| limits.time),
+                    memory_limit: limits.map(|limits| limits.memory),
+                };
+                let result = judge(status, resource_usage, violation, judge_opt);
+                report.result = Some(result.result);
+            } else if let Some(violation) = violation {
+                report.result = Some(ResultKind::SecurityViolation(violation));
+            }
+
+            if let Some(report_path) = &option.report_path {
+                if let Err(err) = report.save(report_path) {
+                    eprintln!("{}", err);
+                }
+            }
 
-            status
+            (status, tracer, Some(report))
         }
         Err(err) => {
             eprintln!("{:?}", err);
-            return ExitCode::UNKNOWN as i32;
+            (ExitCode::Unknown as i32, None, None)
         }
     }
 }
@@ -1,3 +1,3 @@
 pub enum ExitCode {
-    UNKNOWN = 50000,
+    Unknown = 50000,
 }
@@ -1,26 +1,36 @@
 use std::{
     fs::File,
-    io::{BufRead, BufReader},
+    io::{BufRead, BufReader, Cursor},
 };
 
+use serde::Serialize;
+
 use crate::executor::ResourceUsage;
+use crate::trace::SyscallViolation;
 
 pub struct JudgeOption {
-    pub memory_limit: u64,
-    pub time_limit: u64,
+    // only checked when given
+    pub memory_limit: Option<u64>,
+    pub time_limit: Option<u64>,
     pub output_path: Option<String>,
     pub answer_path: Option<String>,
+    // in place of the files, when the output was captured or the answer given as bytes
+    pub output: Option<Vec<u8>>,
+    pub answer: Option<Vec<u8>>,
 }
 
-#[derive(Debug, Clone)]
+#[allow(dead_code)]
+#[derive(Debug, Clone, Serialize)]
 pub enum ResultKind {
     Accepted,
     WrongAnswer,
     TimeLimitExceeded,
     MemoryLimitExceeded,
     RuntimeError,
+    SecurityViolation(SyscallViolation),
 }
 
+#[allow(dead_code)]
 #[derive(Debug, Clone)]
 pub struct JudgeResult {
     pub result: ResultKind,
@@ -34,17 +44,34 @@ fn trim_last_newline(mut vec: Vec<String>) -> Vec<String> {
 }
 
 fn to_bytes(vec: Vec<String>) -> Vec<u8> {
-    vec.into_iter().map(|s| s.into_bytes()).flatten().collect()
+    vec.into_iter().flat_map(|s| s.into_bytes()).collect()
 }
 
-pub fn judge(exit_code: i32, rusage: ResourceUsage, option: JudgeOption) -> JudgeResult {
-    if rusage.user_time.as_millis() as u64 > (option.time_limit * 1000) {
+pub fn judge(
+    exit_code: i32,
+    rusage: ResourceUsage,
+    violation: Option<SyscallViolation>,
+    option: JudgeOption,
+) -> JudgeResult {
+    if let Some(violation) = violation {
+        return JudgeResult {
+            result: ResultKind::SecurityViolation(violation),
+        };
+    }
+
+    if option
+        .time_limit
+        .is_some_and(|limit| rusage.user_time.as_millis() as u64 > limit * 1000)
+    {
         return JudgeResult {
             result: ResultKind::TimeLimitExceeded,
         };
     }
 
-    if rusage.memory > (option.memory_limit / 1024) {
+    if option
+        .memory_limit
+        .is_some_and(|limit| rusage.memory > limit / 1024)
+    {
         return JudgeResult {
             result: ResultKind::MemoryLimitExceeded,
         };
@@ -56,20 +83,21 @@ pub fn judge(exit_code: i32, rusage: ResourceUsage, option: JudgeOption) -> Judg
         };
     }
 
-    let output_path = option.output_path.unwrap();
-    let answer_path = option.answer_path.unwrap();
-    let result = diff(&output_path, &answer_path);
+    let output_reader = reader(option.output, option.output_path);
+    let answer_reader = reader(option.answer, option.answer_path);
+    let result = diff_lines(output_reader, answer_reader);
 
     JudgeResult { result }
 }
 
-pub fn diff(output_path: &str, answer_path: &str) -> ResultKind {
-    let output_file = File::open(output_path).unwrap();
-    let answer_file = File::open(answer_path).unwrap();
-
-    let output_reader = BufReader::new(output_file);
-    let answer_reader = BufReader::new(answer_file);
+fn reader(data: Option<Vec<u8>>, path: Option<String>) -> Box<dyn BufRead> {
+    match data {
+        Some(data) => Box::new(Cursor::new(data)),
+        None => Box::new(BufReader::new(File::open(path.unwrap()).unwrap())),
+    }
+}
 
+fn diff_lines(output_reader: impl BufRead, answer_reader: impl BufRead) -> ResultKind {
     let output_lines: Vec<String> = output_reader
         .lines()
         .map(|l| l.expect("failed to parse line"))
@@ -93,5 +121,5 @@ pub fn diff(output_path: &str, answer_path: &str) -> ResultKind {
         }
         i += 1;
     }
-    return ResultKind::Accepted;
+    ResultKind::Accepted
 }
@@ -0,0 +1,226 @@
+use std::{ffi::CString, os::unix::io::RawFd, path::Path};
+
+use nix::{
+    fcntl::OFlag,
+    libc,
+    unistd::{close, pipe2, read, write},
+};
+use serde::Serialize;
+
+use crate::{mount::Mount, process::Directory};
+
+// LANDLOCK_ACCESS_FS_*, REFER came with ABI 2, TRUNCATE with 3 and IOCTL_DEV with 5
+const EXECUTE: u64 = 1 << 0;
+const WRITE_FILE: u64 = 1 << 1;
+const READ_FILE: u64 = 1 << 2;
+const READ_DIR: u64 = 1 << 3;
+const ABI_1: u64 = (1 << 13) - 1;
+const REFER: u64 = 1 << 13;
+const TRUNCATE: u64 = 1 << 14;
+const IOCTL_DEV: u64 = 1 << 15;
+
+const READ: u64 = EXECUTE | READ_FILE | READ_DIR;
+// the only rights a rule on a file rather than a directory may have
+const FILE: u64 = EXECUTE | WRITE_FILE | READ_FILE | TRUNCATE | IOCTL_DEV;
+
+const CREATE_RULESET_VERSION: libc::c_uint = 1;
+const RULE_PATH_BENEATH: libc::c_int = 1;
+
+// what the host keeps its toolchains and runtimes in, when the program has no root of its own
+const SYSTEM_PATHS: [&str; 9] = [
+    "/bin", "/sbin", "/usr", "/lib", "/lib32", "/lib64", "/etc", "/opt", "/proc",
+];
+const DEVICES: [&str; 3] = ["/dev/null", "/dev/zero", "/dev/urandom"];
+
+#[repr(C)]
+struct RulesetAttr {
+    handled_access_fs: u64,
+}
+
+#[repr(C, packed)]
+struct PathBeneathAttr {
+    allowed_access: u64,
+    parent_fd: i32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LandlockStatus {
+    Enforced { abi: u32 },
+    // the kernel has no Landlock, the program ran without it
+    Unsupported,
+}
+
+// the Landlock ABI version of the kernel, None when it has none or it is disabled
+pub fn abi() -> Option<u32> {
+    let version = unsafe {
+        libc::syscall(
+            libc::SYS_landlock_create_ruleset,
+            std::ptr::null::<RulesetAttr>(),
+            0,
+            CREATE_RULESET_VERSION,
+        )
+    };
+    match version {
+        version if version > 0 => Some(version as u32),
+        _ => None,
+    }
+}
+
+// the child reports what restrict enforced through it, the parent reads it once the child is
+// gone and finds nothing when the child never got that far
+pub fn status_pipe() -> (RawFd, RawFd) {
+    pipe2(OFlag::O_CLOEXEC | OFlag::O_NONBLOCK).expect("creating pipe failed")
+}
+
+pub fn send_status(fd: RawFd, status: &LandlockStatus) {
+    let abi = match status {
+        LandlockStatus::Enforced { abi } => *abi,
+        LandlockStatus::Unsupported => 0,
+    };
+    write(fd, &abi.to_ne_bytes()).expect("sending the landlock status failed");
+    close(fd).ok();
+}
+
+pub fn receive_status(fd: RawFd) -> Option<LandlockStatus> {
+    let mut abi = [0u8; 4];
+    let read = read(fd, &mut abi);
+    close(fd).ok();
+    match (read, u32::from_ne_bytes(abi)) {
+        (Ok(4), 0) => Some(LandlockStatus::Unsupported),
+        (Ok(4), abi) => Some(LandlockStatus::Enforced { abi }),
+        _ => None,
+    }
+}
+
+fn handled_access(abi: u32) -> u64 {
+    let mut access = ABI_1;
+    if abi >= 2 {
+        access |= REFER;
+    }
+    if abi >= 3 {
+        access |= TRUNCATE;
+    }
+    if abi >= 5 {
+        access |= IOCTL_DEV;
+    }
+    access
+}
+
+// read access to the paths the program's root is made of or the host's system paths, and
+// read-write access to its working directory and writable mounts, all as the program sees them
+fn rules(directory: &Directory, program: &Path) -> (Vec<String>, Vec<String>) {
+    let switches_root = directory.switches_root();
+    let mut read: Vec<String> = match switches_root {
+        true => vec!["/".to_string()],
+        false => SYSTEM_PATHS.iter().map(|path| path.to_string()).collect(),
+    };
+    read.push(program.to_string_lossy().into_owned());
+
+    // already changed into the working directory, the judge's own or the new root's otherwise
+    let mut write = vec![];
+    if directory.working_dir.is_some() {
+        write.push(".".to_string());
+    }
+    if directory.overlay.is_some() {
+        write.push("/".to_string());
+    }
+    for mount in &directory.mounts {
+        let target = mount.target().to_string_lossy().into_owned();
+        match mount {
+            Mount::Bind { writable: true, .. } | Mount::Tmpfs { .. } => write.push(target),
+            Mount::Dev { .. } => write.extend(DEVICES.iter().map(|device| {
+                Path::new(&target)
+                    .join(Path::new(device).file_name().unwrap())
+                    .to_string_lossy()
+                    .into_owned()
+            })),
+            _ => {}
+        }
+    }
+    if !switches_root {
+        write.extend(DEVICES.iter().map(|device| device.to_string()));
+    }
+
+    (read, write)
+}
+
+fn add_rule(ruleset: i32, path: &str, access: u64) {
+    let c_path = CString::new(path).unwrap();
+    let fd = unsafe { libc::open(c_path.as_ptr(), libc::O_PATH | libc::O_CLOEXEC) };
+    // a path missing from this system or layout has nothing to allow
+    if fd < 0 {
+        return;
+    }
+
+    let mut stat: libc::stat = unsafe { std::mem::zeroed() };
+    unsafe { libc::fstat(fd, &mut stat) };
+    let access = match stat.st_mode & libc::S_IFMT == libc::S_IFDIR {
+        true => access,
+        false => access & FILE,
+    };
+
+    let rule = PathBeneathAttr {
+        allowed_access: access,
+        parent_fd: fd,
+    };
+    let ret = unsafe {
+        libc::syscall(
+            libc::SYS_landlock_add_rule,
+            ruleset,
+            RULE_PATH_BENEATH,
+            &rule as *const PathBeneathAttr,
+            0,
+        )
+    };
+    unsafe { libc::close(fd) };
+    if ret != 0 {
+        panic!(
+            "adding landlock rule for {} failed: {}",
+            path,
+            nix::errno::Errno::last()
+        );
+    }
+}
+
+// needs no_new_privs, and does nothing on kernels without Landlock
+pub fn restrict(directory: &Directory, program: &Path) -> LandlockStatus {
+    let abi = match abi() {
+        Some(abi) => abi,
+        None => return LandlockStatus::Unsupported,
+    };
+    let handled = handled_access(abi);
+
+    let attr = RulesetAttr {
+        handled_access_fs: handled,
+    };
+    let ruleset = unsafe {
+        libc::syscall(
+            libc::SYS_landlock_create_ruleset,
+            &attr as *const RulesetAttr,
+            std::mem::size_of::<RulesetAttr>(),
+            0,
+        )
+    } as i32;
+    if ruleset < 0 {
+        panic!(
+            "creating landlock ruleset failed: {}",
+            nix::errno::Errno::last()
+        );
+    }
+
+    let (read, write) = rules(directory, program);
+    for path in &read {
+        add_rule(ruleset, path, READ);
+    }
+    for path in &write {
+        add_rule(ruleset, path, handled);
+    }
+
+    let ret = unsafe { libc::syscall(libc::SYS_landlock_restrict_self, ruleset, 0) };
+    unsafe { libc::close(ruleset) };
+    if ret != 0 {
+        panic!("landlock failed: {}", nix::errno::Errno::last());
+    }
+    LandlockStatus::Enforced { abi }
+}
@@ -0,0 +1,16 @@
+pub mod archive;
+pub mod cache;
+pub mod cgroup;
+pub mod command;
+pub mod executor;
+mod exit_code;
+pub mod judge;
+pub mod landlock;
+pub mod mount;
+pub mod namespace;
+pub mod pipe;
+pub mod process;
+pub mod report;
+pub mod seccomp;
+pub mod supervisor;
+pub mod trace;
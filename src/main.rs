@@ -3,17 +3,80 @@ extern crate core;
 extern crate nix;
 
 mod cli;
-mod command;
-mod executor;
-mod exit_code;
-mod judge;
-mod process;
-pub mod seccomp;
 
-use std::path::PathBuf;
+use std::{
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
 
-use command::{compile, run, CompileOption, RunOption};
-use process::Directory;
+use clap::ArgMatches;
+
+use sandbox::{
+    archive::ExtractLimit,
+    cache::{default_cache_dir, CompileCache},
+    command::{compile, learn, run, CompileOption, LearnOption, RunOption},
+    landlock::LandlockStatus,
+    mount::{load_mounts, Mount, Overlay},
+    process::{Credentials, Directory},
+    report::RunReport,
+    supervisor::{MediationAction, SupervisorPolicy},
+};
+
+fn print_report(report: &RunReport) {
+    println!(
+        "user time: {} ms, system time: {} ms, memory: {} KB",
+        report.user_time_ms, report.cpu_time_ms, report.memory_kb
+    );
+    println!("exit code : {}", report.status);
+    if let Some(LandlockStatus::Unsupported) = report.landlock {
+        println!("landlock is not supported by this kernel, ran without it");
+    }
+    // what --capture-stderr kept goes where the program's stderr would have
+    if let Some(stderr) = &report.stderr {
+        io::stderr().write_all(&stderr.data).ok();
+        if stderr.truncated {
+            let newline = if stderr.data.ends_with(b"\n") {
+                ""
+            } else {
+                "\n"
+            };
+            eprintln!("{}[stderr truncated]", newline);
+        }
+    }
+    if let Some(result) = &report.result {
+        println!("{:?}", result);
+    }
+}
+
+// unprivileged users can only sandbox from inside a user namespace
+fn rootless(matches: &ArgMatches) -> bool {
+    matches.is_present("rootless") || !nix::unistd::geteuid().is_root()
+}
+
+fn user(matches: &ArgMatches) -> Option<Credentials> {
+    matches
+        .value_of("user")
+        .map(|user| user.parse().unwrap_or_else(|err| panic!("{}", err)))
+}
+
+// mounts of the --mount-spec file come before those given with --mount
+fn directory(matches: &ArgMatches) -> Directory {
+    let mut mounts: Vec<Mount> = match matches.value_of("mount_spec") {
+        Some(path) => load_mounts(Path::new(path)).unwrap_or_else(|err| panic!("{}", err)),
+        None => vec![],
+    };
+    for mount in matches.values_of("mount").unwrap_or_default() {
+        mounts.push(mount.parse().unwrap_or_else(|err| panic!("{}", err)));
+    }
+
+    Directory {
+        working_dir: matches.value_of("workdir").map(PathBuf::from),
+        root_dir: matches.value_of("rootdir").map(PathBuf::from),
+        mounts,
+        overlay: None,
+        landlock: matches.is_present("landlock"),
+    }
+}
 
 fn main() {
     let matches = cli::init().get_matches();
@@ -28,11 +91,57 @@ fn main() {
                 .unwrap_or("15")
                 .parse()
                 .unwrap();
+            let directory = directory(sub_matches);
+            let cache = match sub_matches.is_present("no_cache") {
+                true => None,
+                false => {
+                    let cache_dir = match sub_matches.value_of("cache_dir") {
+                        Some(path) => PathBuf::from(path),
+                        None => default_cache_dir(),
+                    };
+                    let cache_size: u64 = sub_matches
+                        .value_of("cache_size")
+                        .unwrap_or("1073741824")
+                        .parse()
+                        .unwrap();
+                    Some(CompileCache::new(cache_dir, cache_size))
+                }
+            };
+            let values = |name| -> Vec<String> {
+                sub_matches
+                    .values_of(name)
+                    .unwrap_or_default()
+                    .map(str::to_string)
+                    .collect()
+            };
             let option = CompileOption {
                 language,
                 input_path,
                 output_path,
                 time_limit,
+                directory,
+                cache,
+                extra_flags: values("flag"),
+                defines: values("define"),
+                extra_sources: values("source"),
+                grader_dir: sub_matches.value_of("grader").map(str::to_string),
+                grader_include: sub_matches.value_of("grader_include").map(str::to_string),
+                extract_dir: sub_matches.value_of("extract_dir").map(str::to_string),
+                archive_limit: ExtractLimit {
+                    max_entries: sub_matches
+                        .value_of("archive_entries")
+                        .unwrap_or("1000")
+                        .parse()
+                        .unwrap(),
+                    max_size: sub_matches
+                        .value_of("archive_size")
+                        .unwrap_or("67108864")
+                        .parse()
+                        .unwrap(),
+                },
+                syscall_profile: sub_matches.value_of("seccomp_profile").map(str::to_string),
+                rootless: rootless(sub_matches),
+                user: user(sub_matches),
             };
 
             let succeed = compile(option);
@@ -45,58 +154,129 @@ fn main() {
         Some(("run", sub_matches)) => {
             let language = sub_matches.value_of("language").unwrap().to_string();
             let file_path = sub_matches.value_of("file").unwrap().to_string();
-            let input_path = match sub_matches.value_of("input") {
-                Some(input) => Some(input.to_string()),
-                None => None,
-            };
-            let output_path = match sub_matches.value_of("output") {
-                Some(output) => Some(output.to_string()),
-                None => None,
-            };
-            let answer_path = match sub_matches.value_of("answer") {
-                Some(answer) => Some(answer.to_string()),
-                None => None,
-            };
+            let input_path = sub_matches.value_of("input").map(str::to_string);
+            let output_path = sub_matches.value_of("output").map(str::to_string);
+            let answer_path = sub_matches.value_of("answer").map(str::to_string);
+            let output_limit = sub_matches
+                .value_of("output_limit")
+                .map(|limit| limit.parse().unwrap());
+            let error_path = sub_matches.value_of("error").map(str::to_string);
+            let error_limit = sub_matches
+                .value_of("capture_stderr")
+                .map(|limit| limit.parse().unwrap());
             let time_limit: u64 = sub_matches.value_of("time_limit").unwrap().parse().unwrap();
             let memory_limit: u64 = sub_matches
                 .value_of("memory_limit")
                 .unwrap()
                 .parse()
                 .unwrap();
-            let working_dir = match sub_matches.value_of("workdir") {
-                Some(path) => Some(PathBuf::from(path)),
-                _ => None,
-            };
-            let root_dir = match sub_matches.value_of("rootdir") {
-                Some(path) => Some(PathBuf::from(path)),
-                _ => None,
-            };
-            let directory = Directory {
-                working_dir,
-                root_dir,
-            };
+            let mut directory = directory(sub_matches);
+            if sub_matches.is_present("overlay") {
+                directory.overlay = Some(Overlay {
+                    size: sub_matches
+                        .value_of("overlay_size")
+                        .unwrap_or("67108864")
+                        .parse()
+                        .unwrap(),
+                    archive_path: sub_matches.value_of("overlay_archive").map(PathBuf::from),
+                });
+            }
             let envs: Vec<_> = sub_matches.values_of("env").unwrap_or_default().collect();
             let envs = envs.iter().map(|s| s.to_string()).collect();
+            let supervisor = match sub_matches.is_present("mediate")
+                || sub_matches.is_present("mediate_default")
+            {
+                true => {
+                    let default_action = sub_matches
+                        .value_of("mediate_default")
+                        .map_or(MediationAction::Deny, |action| action.parse().unwrap());
+                    let mut policy = SupervisorPolicy::new(default_action);
+                    policy.rules = sub_matches
+                        .values_of("mediate")
+                        .unwrap_or_default()
+                        .map(|rule| rule.parse().unwrap())
+                        .collect();
+                    Some(policy)
+                }
+                false => None,
+            };
 
             let option = RunOption {
                 language,
                 file_path,
                 input_path,
                 output_path,
+                output_limit,
                 answer_path,
+                error_path,
+                error_limit,
                 time_limit,
                 memory_limit,
                 envs,
                 directory,
+                syscall_profile: sub_matches.value_of("seccomp_profile").map(str::to_string),
+                syscall_default: sub_matches
+                    .value_of("seccomp_default")
+                    .map(|action| action.parse().unwrap()),
+                supervisor,
+                syscall_stats: sub_matches.is_present("syscall_stats"),
+                report_path: sub_matches.value_of("report").map(str::to_string),
+                namespaces: sub_matches
+                    .value_of("namespaces")
+                    .map(|namespaces| namespaces.parse().unwrap()),
+                rootless: rootless(sub_matches),
+                user: user(sub_matches),
+                pass_fds: sub_matches
+                    .values_of("pass_fd")
+                    .unwrap_or_default()
+                    .map(|fd| fd.parse().unwrap())
+                    .collect(),
             };
 
-            let succeed = run(option);
+            let succeed = match run(option) {
+                Ok(report) => {
+                    print_report(&report);
+                    report.status
+                }
+                Err(status) => status,
+            };
             if succeed == 0 {
                 println!("run ok");
             } else {
                 println!("run fail");
             }
         }
+        Some(("profile", sub_matches)) => {
+            if let Some(("learn", sub_matches)) = sub_matches.subcommand() {
+                let option = LearnOption {
+                    file_path: sub_matches.value_of("file").unwrap().to_string(),
+                    args: sub_matches
+                        .values_of("arg")
+                        .unwrap_or_default()
+                        .map(str::to_string)
+                        .collect(),
+                    input_path: sub_matches.value_of("input").map(str::to_string),
+                    output_path: sub_matches.value_of("output").map(str::to_string),
+                    profile_path: sub_matches.value_of("profile").unwrap().to_string(),
+                    time_limit: sub_matches.value_of("time_limit").unwrap().parse().unwrap(),
+                    memory_limit: sub_matches
+                        .value_of("memory_limit")
+                        .unwrap()
+                        .parse()
+                        .unwrap(),
+                    envs: sub_matches
+                        .values_of("env")
+                        .unwrap_or_default()
+                        .map(str::to_string)
+                        .collect(),
+                    directory: directory(sub_matches),
+                    rootless: rootless(sub_matches),
+                    user: user(sub_matches),
+                };
+
+                learn(option);
+            }
+        }
         _ => {
             unreachable!("no valid subcommand given")
         }
@@ -0,0 +1,454 @@
+use std::{
+    ffi::{CString, OsStr},
+    fs, io,
+    os::unix::{ffi::OsStrExt, fs::symlink},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use nix::{
+    libc,
+    mount::{mount, umount2, MntFlags, MsFlags},
+    sched::{unshare, CloneFlags},
+    unistd::{self, fork, ForkResult},
+};
+use serde::Deserialize;
+
+use crate::{
+    archive::pack,
+    namespace::{exit_with, wait_exit},
+};
+
+// device nodes bound into the sandbox's /dev
+const DEVICES: [&str; 3] = ["null", "zero", "urandom"];
+
+// where a run builds its overlay and new root, or a build extracts an archive, made before the
+// sandbox forks so that the caller removes it once the run is over
+pub struct ScratchDir(PathBuf);
+
+impl ScratchDir {
+    pub fn new() -> Self {
+        ScratchDir::new_in(&std::env::temp_dir()).expect("creating the scratch directory failed")
+    }
+
+    // a directory no one else has, named sandbox- and a random suffix
+    pub fn new_in(parent: &Path) -> io::Result<Self> {
+        let template = parent.join("sandbox-XXXXXX");
+        let template = CString::new(template.as_os_str().as_bytes())
+            .unwrap()
+            .into_raw();
+        let created = unsafe { libc::mkdtemp(template) };
+        let path = unsafe { CString::from_raw(template) };
+        if created.is_null() {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(ScratchDir(PathBuf::from(OsStr::from_bytes(
+            path.as_bytes(),
+        ))))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+
+    // left to whoever made it to remove, without going into what was mounted there
+    pub fn into_path(self) -> PathBuf {
+        let path = self.0.clone();
+        std::mem::forget(self);
+        path
+    }
+
+    fn overlay(dir: &Path) -> PathBuf {
+        dir.join("overlay")
+    }
+
+    fn root(dir: &Path) -> PathBuf {
+        dir.join("root")
+    }
+}
+
+impl Default for ScratchDir {
+    fn default() -> Self {
+        ScratchDir::new()
+    }
+}
+
+// the mounts are only in the sandbox's namespace, so here the directories are empty, and one
+// that is not is left alone
+impl Drop for ScratchDir {
+    fn drop(&mut self) {
+        fs::remove_dir(ScratchDir::overlay(&self.0)).ok();
+        fs::remove_dir(ScratchDir::root(&self.0)).ok();
+        fs::remove_dir(&self.0).ok();
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum Mount {
+    // read-only unless writable, at the same path when no target is given
+    Bind {
+        source: PathBuf,
+        target: Option<PathBuf>,
+        #[serde(default)]
+        writable: bool,
+    },
+    Tmpfs {
+        target: PathBuf,
+        // in bytes, half of the memory when not given
+        size: Option<u64>,
+    },
+    // only null, zero and urandom
+    Dev {
+        #[serde(default = "default_dev")]
+        target: PathBuf,
+    },
+    // of the sandbox's pid namespace
+    Proc {
+        #[serde(default = "default_proc")]
+        target: PathBuf,
+    },
+}
+
+fn default_dev() -> PathBuf {
+    PathBuf::from("/dev")
+}
+
+fn default_proc() -> PathBuf {
+    PathBuf::from("/proc")
+}
+
+// a throwaway writable layer over the root directory
+#[derive(Debug, Clone)]
+pub struct Overlay {
+    // of the tmpfs the program's writes go to
+    pub size: u64,
+    // a .tar or .tar.gz to keep the writes in
+    pub archive_path: Option<PathBuf>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MountFile {
+    #[serde(default)]
+    mounts: Vec<Mount>,
+}
+
+// ro:<source>[:<target>], rw:<source>[:<target>], tmpfs:<target>[:<size>], dev[:<target>]
+// or proc[:<target>]
+impl FromStr for Mount {
+    type Err = String;
+
+    fn from_str(spec: &str) -> Result<Self, Self::Err> {
+        let mut parts = spec.splitn(3, ':');
+        let kind = parts.next().unwrap();
+        let first = parts.next().map(PathBuf::from);
+        let second = parts.next();
+
+        let mount = match (kind, first) {
+            ("ro", Some(source)) | ("rw", Some(source)) => Mount::Bind {
+                source,
+                target: second.map(PathBuf::from),
+                writable: kind == "rw",
+            },
+            ("tmpfs", Some(target)) => Mount::Tmpfs {
+                target,
+                size: match second {
+                    Some(size) => Some(
+                        size.parse()
+                            .map_err(|_| format!("invalid tmpfs size: {}", size))?,
+                    ),
+                    None => None,
+                },
+            },
+            ("dev", target) if second.is_none() => Mount::Dev {
+                target: target.unwrap_or_else(default_dev),
+            },
+            ("proc", target) if second.is_none() => Mount::Proc {
+                target: target.unwrap_or_else(default_proc),
+            },
+            _ => return Err(format!("invalid mount: {}", spec)),
+        };
+        mount.validate()?;
+        Ok(mount)
+    }
+}
+
+impl Mount {
+    pub fn target(&self) -> &Path {
+        match self {
+            Mount::Bind { source, target, .. } => target.as_deref().unwrap_or(source),
+            Mount::Tmpfs { target, .. } | Mount::Dev { target } | Mount::Proc { target } => target,
+        }
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if let Mount::Bind { source, .. } = self {
+            if !source.is_absolute() {
+                return Err(format!(
+                    "bind source must be absolute: {}",
+                    source.display()
+                ));
+            }
+        }
+        let target = self.target();
+        if !target.is_absolute() || target.components().any(|c| c.as_os_str() == "..") {
+            return Err(format!(
+                "mount target must be absolute: {}",
+                target.display()
+            ));
+        }
+        Ok(())
+    }
+
+    // mount points are only made on the sandbox's own file systems, a shared directory of the
+    // host must already have them
+    fn apply(&self, new_root: &Path, create: bool) {
+        let target = new_root.join(self.target().strip_prefix("/").unwrap());
+        if !create && !target.exists() {
+            panic!(
+                "nothing to mount {} over, it is not made in a shared directory",
+                self.target().display()
+            );
+        }
+
+        match self {
+            Mount::Bind {
+                source, writable, ..
+            } => {
+                mount_point(source, &target);
+                bind(source, &target);
+                if !writable {
+                    read_only(&target, true);
+                }
+            }
+            Mount::Tmpfs { size, .. } => {
+                let options = match size {
+                    Some(size) => format!("mode=1777,size={}", size),
+                    None => "mode=1777".to_string(),
+                };
+                tmpfs(&target, &options);
+            }
+            Mount::Dev { .. } => {
+                tmpfs(&target, "mode=755,size=65536");
+                for device in DEVICES {
+                    let source = Path::new("/dev").join(device);
+                    let target = target.join(device);
+                    mount_point(&source, &target);
+                    bind(&source, &target);
+                }
+                for (name, link) in [
+                    ("fd", "/proc/self/fd"),
+                    ("stdin", "/proc/self/fd/0"),
+                    ("stdout", "/proc/self/fd/1"),
+                    ("stderr", "/proc/self/fd/2"),
+                ] {
+                    symlink(link, target.join(name)).expect("creating /dev link failed");
+                }
+            }
+            Mount::Proc { .. } => {
+                fs::create_dir_all(&target).expect("creating mount point failed");
+                mount(
+                    Some("proc"),
+                    &target,
+                    Some("proc"),
+                    MsFlags::MS_NOSUID | MsFlags::MS_NODEV | MsFlags::MS_NOEXEC,
+                    None::<&str>,
+                )
+                .expect("mounting proc failed, it needs the pid namespace");
+            }
+        }
+    }
+}
+
+impl Overlay {
+    // returns the merged root, with lower as its read-only layer, and the upper layer that
+    // takes the writes
+    pub fn mount(&self, lower: &Path, scratch: &Path) -> (PathBuf, PathBuf) {
+        let dir = ScratchDir::overlay(scratch);
+        tmpfs(&dir, &format!("mode=755,size={}", self.size));
+        let (upper, work, merged) = (dir.join("upper"), dir.join("work"), dir.join("merged"));
+        for path in [&upper, &work, &merged] {
+            fs::create_dir(path).expect("creating overlay directory failed");
+        }
+
+        let options = format!(
+            "lowerdir={},upperdir={},workdir={}",
+            lower.display(),
+            upper.display(),
+            work.display()
+        );
+        if let Err(err) = mount(
+            Some("overlay"),
+            &merged,
+            Some("overlay"),
+            MsFlags::empty(),
+            Some(options.as_str()),
+        ) {
+            panic!("mounting overlay on {} failed: {}", lower.display(), err);
+        }
+
+        (merged, upper)
+    }
+
+    // the program goes on in a mount namespace of its own to switch roots in, while this
+    // process keeps the upper layer and archives it once the program exits
+    pub fn keep(&self, upper: &Path) {
+        let archive_path = match &self.archive_path {
+            Some(archive_path) => archive_path,
+            None => return,
+        };
+
+        match unsafe { fork() }.expect("fork failed") {
+            ForkResult::Parent { child } => {
+                let status = wait_exit(child);
+                if let Err(err) = pack(upper, archive_path) {
+                    eprintln!("archiving the overlay failed: {}", err);
+                }
+                exit_with(status);
+            }
+            ForkResult::Child => {
+                unsafe { libc::prctl(libc::PR_SET_PDEATHSIG, libc::SIGKILL) };
+                unshare(CloneFlags::CLONE_NEWNS).expect("unshare failed");
+            }
+        }
+    }
+}
+
+pub fn load_mounts(path: &Path) -> Result<Vec<Mount>, String> {
+    let content = fs::read_to_string(path).map_err(|err| format!("{}: {}", path.display(), err))?;
+    let file: MountFile = if path.extension() == Some("json".as_ref()) {
+        serde_json::from_str(&content).map_err(|err| format!("{}: {}", path.display(), err))?
+    } else {
+        toml::from_str(&content).map_err(|err| format!("{}: {}", path.display(), err))?
+    };
+
+    for mount in &file.mounts {
+        mount
+            .validate()
+            .map_err(|err| format!("{}: {}", path.display(), err))?;
+    }
+    Ok(file.mounts)
+}
+
+// makes root_dir, or the mount layout built on top of it or on an empty root, the root and
+// detaches the old one, so that nothing outside is reachable, not even through ..
+pub fn pivot(root_dir: Option<&Path>, mounts: &[Mount], writable: bool, scratch: &Path) {
+    let new_root = ScratchDir::root(scratch);
+    fs::create_dir_all(&new_root).expect("creating the new root failed");
+
+    match root_dir {
+        Some(root_dir) => bind(root_dir, &new_root),
+        None => tmpfs(&new_root, "mode=755,size=1048576"),
+    }
+    // a layout's base is read-only unless it is a throwaway overlay, a shared root_dir along with
+    // its submounts and before the layout goes on top, an empty root only once the mount points
+    // are made in it
+    let read_only_base = !mounts.is_empty() && !writable;
+    let shared = root_dir.is_some() && !writable;
+    if read_only_base && shared {
+        read_only(&new_root, true);
+    }
+    for (index, mount) in mounts.iter().enumerate() {
+        mount.apply(
+            &new_root,
+            creatable(mount.target(), &mounts[..index], !shared),
+        );
+    }
+    if read_only_base && !shared {
+        read_only(&new_root, false);
+    }
+
+    unistd::chdir(&new_root).expect("chdir failed");
+    unistd::pivot_root(".", ".").expect("pivot_root failed");
+    // the old root is stacked under the new one until it is detached
+    umount2(".", MntFlags::MNT_DETACH).expect("detaching the old root failed");
+    unistd::chdir("/").expect("chdir failed");
+}
+
+// whether the file system target is on is one of the sandbox's own, given the mounts before it
+fn creatable(target: &Path, before: &[Mount], base: bool) -> bool {
+    // the last mount above the target hides whatever was mounted there earlier
+    before
+        .iter()
+        .rev()
+        .find(|mount| target.starts_with(mount.target()) && target != mount.target())
+        .map_or(base, |mount| {
+            matches!(mount, Mount::Tmpfs { .. } | Mount::Dev { .. })
+        })
+}
+
+// an empty directory or file to mount the source over
+fn mount_point(source: &Path, target: &Path) {
+    if target.exists() {
+        return;
+    }
+    let created = if source.is_dir() {
+        fs::create_dir_all(target)
+    } else {
+        fs::create_dir_all(target.parent().unwrap()).and_then(|_| {
+            fs::OpenOptions::new()
+                .write(true)
+                .create(true)
+                .truncate(false)
+                .open(target)
+                .map(|_| ())
+        })
+    };
+    if let Err(err) = created {
+        panic!("creating mount point {} failed: {}", target.display(), err);
+    }
+}
+
+fn bind(source: &Path, target: &Path) {
+    if let Err(err) = mount(
+        Some(source),
+        target,
+        None::<&str>,
+        MsFlags::MS_BIND | MsFlags::MS_REC,
+        None::<&str>,
+    ) {
+        panic!("binding {} failed: {}", source.display(), err);
+    }
+}
+
+// mount_setattr as a remount only changes the top mount, not what is mounted below it
+fn read_only(target: &Path, recursive: bool) {
+    let path = CString::new(target.as_os_str().as_bytes()).unwrap();
+    let mut attr: libc::mount_attr = unsafe { std::mem::zeroed() };
+    attr.attr_set = libc::MOUNT_ATTR_RDONLY;
+    let flags = match recursive {
+        true => libc::AT_RECURSIVE,
+        false => 0,
+    };
+
+    let ret = unsafe {
+        libc::syscall(
+            libc::SYS_mount_setattr,
+            libc::AT_FDCWD,
+            path.as_ptr(),
+            flags,
+            &attr as *const libc::mount_attr,
+            std::mem::size_of::<libc::mount_attr>(),
+        )
+    };
+    if ret != 0 {
+        panic!(
+            "making {} read-only failed: {}",
+            target.display(),
+            nix::errno::Errno::last()
+        );
+    }
+}
+
+fn tmpfs(target: &Path, options: &str) {
+    fs::create_dir_all(target).expect("creating mount point failed");
+    mount(
+        Some("tmpfs"),
+        target,
+        Some("tmpfs"),
+        MsFlags::MS_NOSUID | MsFlags::MS_NODEV,
+        Some(options),
+    )
+    .expect("mounting tmpfs failed");
+}
@@ -0,0 +1,295 @@
+use std::{convert::TryFrom, fs, path::Path, str::FromStr};
+
+use nix::{
+    fcntl::OFlag,
+    libc,
+    mount::{mount, MsFlags},
+    sched::{unshare, CloneFlags},
+    sys::{
+        signal::{self, SigHandler, SigSet, Signal},
+        wait::{waitpid, WaitStatus},
+    },
+    unistd::{self, fork, ForkResult, Pid},
+};
+
+#[derive(Debug, Clone, Default)]
+pub struct Namespaces {
+    pub user: bool,
+    pub pid: bool,
+    pub mount: bool,
+    // an empty network with only the loopback interface
+    pub network: bool,
+    pub ipc: bool,
+    pub uts: bool,
+    pub cgroup: bool,
+}
+
+// a comma separated list of user, pid, mount, net, ipc, uts, cgroup or `all`
+impl FromStr for Namespaces {
+    type Err = String;
+
+    fn from_str(list: &str) -> Result<Self, Self::Err> {
+        let mut namespaces = Namespaces::default();
+        for name in list.split(',') {
+            match name.trim() {
+                "all" => namespaces = Namespaces::all(),
+                "user" => namespaces.user = true,
+                "pid" => namespaces.pid = true,
+                "mount" => namespaces.mount = true,
+                "net" => namespaces.network = true,
+                "ipc" => namespaces.ipc = true,
+                "uts" => namespaces.uts = true,
+                "cgroup" => namespaces.cgroup = true,
+                name => return Err(format!("unknown namespace: {}", name)),
+            }
+        }
+        Ok(namespaces)
+    }
+}
+
+impl Namespaces {
+    pub fn all() -> Self {
+        Namespaces {
+            user: true,
+            pid: true,
+            mount: true,
+            network: true,
+            ipc: true,
+            uts: true,
+            cgroup: true,
+        }
+    }
+
+    // the user namespace stands in for root and the mount namespace lets it switch roots
+    pub fn rootless(mut self) -> Self {
+        self.user = true;
+        self.mount = true;
+        self
+    }
+
+    fn flags(&self) -> CloneFlags {
+        let mut flags = CloneFlags::empty();
+        for (enabled, flag) in [
+            (self.user, CloneFlags::CLONE_NEWUSER),
+            (self.pid, CloneFlags::CLONE_NEWPID),
+            (self.mount, CloneFlags::CLONE_NEWNS),
+            (self.network, CloneFlags::CLONE_NEWNET),
+            (self.ipc, CloneFlags::CLONE_NEWIPC),
+            (self.uts, CloneFlags::CLONE_NEWUTS),
+            (self.cgroup, CloneFlags::CLONE_NEWCGROUP),
+        ] {
+            if enabled {
+                flags |= flag;
+            }
+        }
+        flags
+    }
+
+    // returns None in the process that goes on to run the program, and the program's exit
+    // status in the sandbox's processes around it, which must exit with it
+    pub fn enter(&self, root_dir: Option<&Path>) -> Option<WaitStatus> {
+        let uid = unistd::getuid();
+        let gid = unistd::getgid();
+        unshare(self.flags()).expect("unshare failed");
+
+        if self.user {
+            map_ids(uid.as_raw(), gid.as_raw());
+        }
+        if self.mount {
+            // keep our mounts from propagating back to the host
+            mount(
+                None::<&str>,
+                "/",
+                None::<&str>,
+                MsFlags::MS_REC | MsFlags::MS_PRIVATE,
+                None::<&str>,
+            )
+            .expect("making mounts private failed");
+        }
+        if self.uts {
+            unistd::sethostname("sandbox").expect("sethostname failed");
+        }
+        if self.network {
+            loopback_up();
+        }
+        if !self.pid {
+            return None;
+        }
+
+        // the init cannot die of a signal it sends itself, so it hands the program's on
+        let (relay_read, relay_write) =
+            unistd::pipe2(OFlag::O_CLOEXEC | OFlag::O_NONBLOCK).expect("pipe failed");
+
+        // a new pid namespace only takes the children, the first of which is its init
+        match unsafe { fork() }.expect("fork failed") {
+            ForkResult::Parent { child } => {
+                unistd::close(relay_write).ok();
+                let status = wait_exit(child);
+                let mut signal = [0u8];
+                let relayed = unistd::read(relay_read, &mut signal).ok() == Some(1);
+                unistd::close(relay_read).ok();
+                return match Signal::try_from(signal[0] as i32) {
+                    Ok(signal) if relayed => Some(WaitStatus::Signaled(child, signal, false)),
+                    _ => Some(status),
+                };
+            }
+            ForkResult::Child => {
+                unistd::close(relay_read).ok();
+            }
+        }
+        // the namespace and everything in it goes away with the sandbox
+        unsafe { libc::prctl(libc::PR_SET_PDEATHSIG, libc::SIGKILL) };
+        if self.mount {
+            mount_proc(root_dir);
+        }
+
+        match unsafe { fork() }.expect("fork failed") {
+            ForkResult::Parent { child } => {
+                let status = reap(child);
+                if let WaitStatus::Signaled(_, signal, _) = status {
+                    unistd::write(relay_write, &[signal as u8]).ok();
+                }
+                Some(status)
+            }
+            ForkResult::Child => {
+                unistd::close(relay_write).ok();
+                None
+            }
+        }
+    }
+}
+
+// probes in a throwaway child so that the sandbox itself keeps its namespaces
+pub fn check_user_namespaces() -> Result<(), String> {
+    let child = match unsafe { fork() }.expect("fork failed") {
+        ForkResult::Parent { child } => child,
+        ForkResult::Child => {
+            let code = match unshare(CloneFlags::CLONE_NEWUSER) {
+                Ok(_) => 0,
+                Err(_) => 1,
+            };
+            unsafe { libc::_exit(code) };
+        }
+    };
+    if let WaitStatus::Exited(_, 0) = wait_exit(child) {
+        return Ok(());
+    }
+
+    let sysctls = [
+        ("kernel.unprivileged_userns_clone", "0"),
+        ("user.max_user_namespaces", "0"),
+        ("kernel.apparmor_restrict_unprivileged_userns", "1"),
+    ];
+    let reason = sysctls
+        .iter()
+        .find(|(name, value)| {
+            let path = format!("/proc/sys/{}", name.replace('.', "/"));
+            fs::read_to_string(path).is_ok_and(|current| current.trim() == *value)
+        })
+        .map_or_else(
+            || "creating one failed".to_string(),
+            |(name, value)| format!("{} is {}", name, value),
+        );
+
+    Err(format!(
+        "rootless mode needs unprivileged user namespaces, which this kernel disallows: {}",
+        reason
+    ))
+}
+
+// the sandbox's user becomes root of the new user namespace
+fn map_ids(uid: u32, gid: u32) {
+    fs::write("/proc/self/setgroups", "deny").expect("writing setgroups failed");
+    fs::write("/proc/self/uid_map", format!("0 {} 1", uid)).expect("writing uid_map failed");
+    fs::write("/proc/self/gid_map", format!("0 {} 1", gid)).expect("writing gid_map failed");
+}
+
+fn loopback_up() {
+    let socket = unsafe { libc::socket(libc::AF_INET, libc::SOCK_DGRAM | libc::SOCK_CLOEXEC, 0) };
+    if socket < 0 {
+        panic!("socket failed");
+    }
+
+    let mut request: libc::ifreq = unsafe { std::mem::zeroed() };
+    for (dst, src) in request.ifr_name.iter_mut().zip(b"lo") {
+        *dst = *src as libc::c_char;
+    }
+    let ret = unsafe {
+        if libc::ioctl(socket, libc::SIOCGIFFLAGS, &mut request) == 0 {
+            request.ifr_ifru.ifru_flags |= libc::IFF_UP as libc::c_short;
+            libc::ioctl(socket, libc::SIOCSIFFLAGS, &request)
+        } else {
+            -1
+        }
+    };
+    unsafe { libc::close(socket) };
+    if ret != 0 {
+        panic!("bringing up loopback failed");
+    }
+}
+
+// a proc of the new pid namespace where the program will look for it
+fn mount_proc(root_dir: Option<&Path>) {
+    let target = root_dir.unwrap_or_else(|| Path::new("/")).join("proc");
+    if !target.is_dir() {
+        return;
+    }
+    mount(
+        Some("proc"),
+        &target,
+        Some("proc"),
+        MsFlags::MS_NOSUID | MsFlags::MS_NODEV | MsFlags::MS_NOEXEC,
+        None::<&str>,
+    )
+    .expect("mounting proc failed");
+}
+
+fn exited(status: WaitStatus) -> bool {
+    matches!(status, WaitStatus::Exited(..) | WaitStatus::Signaled(..))
+}
+
+pub fn wait_exit(child: Pid) -> WaitStatus {
+    loop {
+        match waitpid(child, None) {
+            Ok(status) if exited(status) => return status,
+            Ok(_) | Err(nix::Error::Sys(nix::errno::Errno::EINTR)) => {}
+            Err(_) => return WaitStatus::Exited(child, 1),
+        }
+    }
+}
+
+// exits the way the program did, so that whoever waits for the sandbox sees its signal;
+// an init ignores its own signals and falls back to 128 + the number, like a shell
+pub fn exit_with(status: WaitStatus) -> ! {
+    let code = match status {
+        WaitStatus::Exited(_, code) => code,
+        WaitStatus::Signaled(_, signal, _) => {
+            let no_core = libc::rlimit {
+                rlim_cur: 0,
+                rlim_max: 0,
+            };
+            unsafe {
+                libc::setrlimit(libc::RLIMIT_CORE, &no_core);
+                signal::signal(signal, SigHandler::SigDfl).ok();
+            }
+            let mut set = SigSet::empty();
+            set.add(signal);
+            set.thread_unblock().ok();
+            signal::kill(unistd::getpid(), signal).ok();
+            128 + signal as i32
+        }
+        _ => 1,
+    };
+    unsafe { libc::_exit(code) }
+}
+
+// reaps whatever gets orphaned in the namespace until the program exits
+fn reap(program: Pid) -> WaitStatus {
+    loop {
+        match waitpid(None, None) {
+            Ok(status) if status.pid() == Some(program) && exited(status) => return status,
+            Ok(_) | Err(nix::Error::Sys(nix::errno::Errno::EINTR)) => {}
+            Err(_) => return WaitStatus::Exited(program, 1),
+        }
+    }
+}
@@ -0,0 +1,129 @@
+use nix::{
+    errno::Errno,
+    fcntl::OFlag,
+    libc,
+    unistd::{close, pipe2, read, write},
+};
+use serde::{Serialize, Serializer};
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CapturedOutput {
+    #[serde(serialize_with = "lossy")]
+    pub data: Vec<u8>,
+    // the program wrote more than the limit, which was dropped
+    pub truncated: bool,
+}
+
+fn lossy<S: Serializer>(data: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&String::from_utf8_lossy(data))
+}
+
+fn set_nonblocking(fd: i32) {
+    unsafe {
+        let flags = libc::fcntl(fd, libc::F_GETFL);
+        libc::fcntl(fd, libc::F_SETFL, flags | libc::O_NONBLOCK);
+    }
+}
+
+// reads what the program writes to a pipe, keeping up to `limit` bytes and draining the rest
+// so that the program never blocks on a full pipe
+pub struct Capture {
+    fd: i32,
+    limit: usize,
+    data: Vec<u8>,
+    truncated: bool,
+}
+
+impl Capture {
+    // returns the capture and the write end for the child, to be closed by the parent with
+    // `close_write_end` once forked
+    pub fn new(limit: u64) -> (Self, i32) {
+        let (read_fd, write_fd) = pipe2(OFlag::O_CLOEXEC).expect("creating pipe failed");
+        // only the reading end is polled, the child blocks on writing as usual
+        set_nonblocking(read_fd);
+
+        let capture = Capture {
+            fd: read_fd,
+            limit: limit as usize,
+            data: vec![],
+            truncated: false,
+        };
+        (capture, write_fd)
+    }
+
+    pub fn close_write_end(write_fd: i32) {
+        close(write_fd).ok();
+    }
+
+    // reads whatever is in the pipe without blocking
+    pub fn drain(&mut self) {
+        let mut buffer = [0u8; 65536];
+        while let Ok(count) = read(self.fd, &mut buffer) {
+            if count == 0 {
+                break;
+            }
+            let room = self.limit.saturating_sub(self.data.len());
+            if count > room {
+                self.truncated = true;
+            }
+            self.data.extend_from_slice(&buffer[..count.min(room)]);
+        }
+    }
+
+    pub fn finish(mut self) -> CapturedOutput {
+        self.drain();
+        close(self.fd).ok();
+        CapturedOutput {
+            data: self.data,
+            truncated: self.truncated,
+        }
+    }
+}
+
+// writes to the program's stdin as the pipe makes room and closes it once all is written, or
+// once the program stops reading, which takes SIGPIPE being ignored as Rust programs do
+pub struct Feed {
+    fd: Option<i32>,
+    data: Vec<u8>,
+    written: usize,
+}
+
+impl Feed {
+    // returns the feed and the read end for the child, to be closed by the parent once forked
+    pub fn new(data: Vec<u8>) -> (Self, i32) {
+        let (read_fd, write_fd) = pipe2(OFlag::O_CLOEXEC).expect("creating pipe failed");
+        set_nonblocking(write_fd);
+
+        let feed = Feed {
+            fd: Some(write_fd),
+            data,
+            written: 0,
+        };
+        (feed, read_fd)
+    }
+
+    // in the child as well, where a write end kept open by the sandbox's own processes would
+    // keep the program from ever reading the end of its input
+    pub fn close(&mut self) {
+        if let Some(fd) = self.fd.take() {
+            close(fd).ok();
+        }
+    }
+
+    // writes what the pipe takes without blocking
+    pub fn pump(&mut self) {
+        let fd = match self.fd {
+            Some(fd) => fd,
+            None => return,
+        };
+        while self.written < self.data.len() {
+            match write(fd, &self.data[self.written..]) {
+                Ok(count) => self.written += count,
+                Err(nix::Error::Sys(Errno::EAGAIN)) => return,
+                Err(nix::Error::Sys(Errno::EINTR)) => {}
+                Err(_) => break,
+            }
+        }
+        self.close();
+    }
+}
@@ -1,20 +1,115 @@
-use std::{convert::TryInto, ffi::CString, path::PathBuf};
+use std::{
+    convert::TryInto,
+    ffi::{CStr, CString},
+    fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
 
-use nix::{libc, unistd};
+use nix::{libc, sys::ptrace, unistd};
 
-use crate::seccomp::SyscallFilter;
+use crate::{
+    cgroup, landlock,
+    mount::{pivot, Mount, Overlay, ScratchDir},
+    namespace::{self, Namespaces},
+    seccomp::SyscallFilter,
+    supervisor::send_listener,
+};
 
 #[repr(u32)]
 pub enum Resource {
-    AddressSpace = libc::RLIMIT_AS as u32,
-    CPUTime = libc::RLIMIT_CPU as u32,
-    CoreDump = libc::RLIMIT_CORE as u32,
+    AddressSpace = libc::RLIMIT_AS,
+    CPUTime = libc::RLIMIT_CPU,
+    CoreDump = libc::RLIMIT_CORE,
+    FileSize = libc::RLIMIT_FSIZE,
+    Processes = libc::RLIMIT_NPROC,
 }
 
 #[derive(Clone)]
 pub struct Directory {
     pub working_dir: Option<PathBuf>,
     pub root_dir: Option<PathBuf>,
+    // laid out on top of root_dir, which then becomes read-only
+    pub mounts: Vec<Mount>,
+    // keeps root_dir untouched by what the program writes
+    pub overlay: Option<Overlay>,
+    // Landlock rules drawn from the above, where the kernel has it
+    pub landlock: bool,
+}
+
+impl Directory {
+    // the program sees its own root, so switching to it needs a mount namespace of its own
+    pub fn switches_root(&self) -> bool {
+        self.root_dir.is_some() || !self.mounts.is_empty()
+    }
+
+    // adds the namespaces the root switch and a fresh /proc need
+    pub fn namespaces(&self, namespaces: Option<Namespaces>) -> Option<Namespaces> {
+        if !self.switches_root() {
+            return namespaces;
+        }
+        let mut namespaces = namespaces.unwrap_or_default();
+        namespaces.mount = true;
+        if self
+            .mounts
+            .iter()
+            .any(|mount| matches!(mount, Mount::Proc { .. }))
+        {
+            namespaces.pid = true;
+        }
+        Some(namespaces)
+    }
+
+    pub fn host_path(&self, path: &str) -> PathBuf {
+        let path = Path::new(path);
+
+        // the working directory is a path inside the root as well
+        let path = match &self.working_dir {
+            Some(working_dir) if path.is_relative() => working_dir.join(path),
+            _ => path.to_path_buf(),
+        };
+        if !self.switches_root() {
+            return path;
+        }
+
+        let path = Path::new("/").join(path);
+        let bound = self.mounts.iter().rev().find_map(|mount| match mount {
+            Mount::Bind { source, .. } => path
+                .strip_prefix(mount.target())
+                .ok()
+                .map(|rest| source.join(rest)),
+            _ => None,
+        });
+        match (bound, &self.root_dir) {
+            (Some(host_path), _) => host_path,
+            (None, Some(root_dir)) => root_dir.join(path.strip_prefix("/").unwrap()),
+            (None, None) => path,
+        }
+    }
+}
+
+// who the program runs as
+#[derive(Debug, Clone, Copy)]
+pub struct Credentials {
+    pub uid: u32,
+    pub gid: u32,
+}
+
+// <uid>[:<gid>], the gid being the uid when not given
+impl FromStr for Credentials {
+    type Err = String;
+
+    fn from_str(user: &str) -> Result<Self, Self::Err> {
+        let parse = |id: &str| {
+            id.parse::<u32>()
+                .map_err(|_| format!("invalid user: {}", user))
+        };
+        let (uid, gid) = match user.split_once(':') {
+            Some((uid, gid)) => (parse(uid)?, parse(gid)?),
+            None => (parse(user)?, parse(user)?),
+        };
+        Ok(Credentials { uid, gid })
+    }
 }
 
 pub struct Process {
@@ -23,9 +118,18 @@ pub struct Process {
     envs: Vec<CString>,
     stdin_fd: i32,
     stdout_fd: i32,
+    stderr_fd: i32,
     limits: Vec<(i32, u64)>,
     dir: Option<Directory>,
     syscall_filters: Option<SyscallFilter>,
+    traced: bool,
+    supervisor: Option<(SyscallFilter, i32)>,
+    namespaces: Option<Namespaces>,
+    user: Option<Credentials>,
+    pass_fds: Vec<i32>,
+    pids_cgroup: Option<PathBuf>,
+    scratch_dir: Option<PathBuf>,
+    landlock_status: Option<i32>,
 }
 
 impl Drop for Process {
@@ -33,6 +137,7 @@ impl Drop for Process {
         unsafe {
             libc::close(self.stdin_fd);
             libc::close(self.stdout_fd);
+            libc::close(self.stderr_fd);
         }
     }
 }
@@ -47,16 +152,25 @@ impl Process {
             envs: vec![],
             stdin_fd: 0,
             stdout_fd: 1,
+            stderr_fd: 2,
             limits: vec![],
             dir: None,
             syscall_filters: None,
+            traced: false,
+            supervisor: None,
+            namespaces: None,
+            user: None,
+            pass_fds: vec![],
+            pids_cgroup: None,
+            scratch_dir: None,
+            landlock_status: None,
         }
     }
 
     pub fn args(mut self, args: Vec<&str>) -> Self {
         self.args = args
             .iter()
-            .map(|arg| CString::new(arg.clone()).unwrap())
+            .map(|arg| CString::new(*arg).unwrap())
             .collect::<Vec<CString>>();
         self
     }
@@ -64,7 +178,7 @@ impl Process {
     pub fn envs(mut self, envs: Vec<String>) -> Self {
         self.envs = envs
             .iter()
-            .map(|env| CString::new(format!("{}", env)).unwrap())
+            .map(|env| CString::new(env.as_str()).unwrap())
             .collect::<Vec<CString>>();
         self
     }
@@ -74,14 +188,35 @@ impl Process {
         self
     }
 
+    // where the overlay and new root are built, which the caller removes after the run
+    pub fn scratch_dir(mut self, path: PathBuf) -> Self {
+        self.scratch_dir = Some(path);
+        self
+    }
+
+    // counts the program's processes in place of Resource::Processes
+    pub fn pids_cgroup(mut self, path: PathBuf) -> Self {
+        self.pids_cgroup = Some(path);
+        self
+    }
+
     fn setrlimit(&self) {
         for (resource, value) in &self.limits {
+            let resource = (*resource).try_into().unwrap();
+            let mut current = libc::rlimit {
+                rlim_cur: 0,
+                rlim_max: libc::RLIM_INFINITY,
+            };
+            unsafe { libc::getrlimit(resource, &mut current) };
+
+            // without root the hard limit can only be lowered
+            let value = (*value).min(current.rlim_max);
             let ret = unsafe {
                 libc::setrlimit(
-                    (*resource).try_into().unwrap(),
+                    resource,
                     &libc::rlimit {
-                        rlim_cur: *value,
-                        rlim_max: *value,
+                        rlim_cur: value,
+                        rlim_max: value,
                     },
                 )
             };
@@ -96,55 +231,174 @@ impl Process {
         self
     }
 
-    fn chroot(&self) {
-        if self.dir.is_none() {
-            return;
-        }
+    fn switch_root(&self) {
+        let directory = match &self.dir {
+            Some(directory) => directory,
+            None => return,
+        };
 
-        let directory = self.dir.as_ref().unwrap();
+        if directory.switches_root() {
+            // without one from the caller, it is left behind
+            let scratch = match &self.scratch_dir {
+                Some(scratch) => scratch.clone(),
+                None => ScratchDir::new().into_path(),
+            };
 
-        if directory.working_dir.is_some() {
-            unistd::chdir(directory.working_dir.as_ref().unwrap().as_path()).unwrap();
+            let mut root_dir = directory.root_dir.clone();
+            if let Some(overlay) = &directory.overlay {
+                let lower = root_dir
+                    .as_deref()
+                    .expect("an overlay needs a root directory");
+                let (merged, upper) = overlay.mount(lower, &scratch);
+                root_dir = Some(merged);
+                overlay.keep(&upper);
+            }
+            pivot(
+                root_dir.as_deref(),
+                &directory.mounts,
+                directory.overlay.is_some(),
+                &scratch,
+            );
         }
-        if directory.root_dir.is_some() {
-            unistd::chroot(directory.root_dir.as_ref().unwrap().as_path()).unwrap();
+        // only now, so that it is resolved inside the new root
+        if let Some(working_dir) = &directory.working_dir {
+            unistd::chdir(working_dir.as_path()).unwrap();
         }
     }
 
-    pub fn use_syscall_filter(mut self, enabled: bool) -> Self {
-        if enabled {
-            self.syscall_filters = Some(SyscallFilter::new());
-        } else {
-            self.syscall_filters = None;
-        }
+    pub fn namespaces(mut self, namespaces: Namespaces) -> Self {
+        self.namespaces = Some(namespaces);
+        self
+    }
 
+    pub fn user(mut self, user: Credentials) -> Self {
+        self.user = Some(user);
         self
     }
 
-    fn apply_syscall_filter(&self) {
-        let filters = self.syscall_filters.as_ref().unwrap();
+    fn drop_privileges(&self) {
+        // no capability can come back, not even by executing something as root or setuid
+        for cap in 0.. {
+            if unsafe { libc::prctl(libc::PR_CAPBSET_DROP, cap, 0, 0, 0) } != 0 {
+                match nix::errno::Errno::last() {
+                    nix::errno::Errno::EINVAL => break,
+                    err => panic!("dropping capability {} failed: {}", cap, err),
+                }
+            }
+        }
+        let ret = unsafe {
+            libc::prctl(
+                libc::PR_CAP_AMBIENT,
+                libc::PR_CAP_AMBIENT_CLEAR_ALL,
+                0,
+                0,
+                0,
+            )
+        };
+        if ret != 0 {
+            panic!("clearing ambient capabilities failed");
+        }
+        // changing from root to another user still takes the capabilities away
+        let securebits = libc::SECBIT_NOROOT
+            | libc::SECBIT_NOROOT_LOCKED
+            | libc::SECBIT_NO_SETUID_FIXUP_LOCKED
+            | libc::SECBIT_KEEP_CAPS_LOCKED
+            | libc::SECBIT_NO_CAP_AMBIENT_RAISE
+            | libc::SECBIT_NO_CAP_AMBIENT_RAISE_LOCKED;
+        if unsafe { libc::prctl(libc::PR_SET_SECUREBITS, securebits, 0, 0, 0) } != 0 {
+            panic!("locking securebits failed: {}", nix::errno::Errno::last());
+        }
 
-        filters.rules.iter().for_each(|(name, action)| {
-            let name = CString::new(name.clone()).unwrap();
-            let syscall_id = unsafe { seccomp_sys::seccomp_syscall_resolve_name(name.as_ptr()) };
-            if syscall_id < 0 {
-                panic!("invalid system call id of {:?}: {}", name, syscall_id);
+        if let Some(user) = &self.user {
+            // a user namespace denies setgroups, it has no groups but its own to keep
+            let in_user_namespace = self.namespaces.as_ref().is_some_and(|ns| ns.user);
+            if !in_user_namespace {
+                unistd::setgroups(&[]).expect("setgroups failed");
             }
+            let gid = unistd::Gid::from_raw(user.gid);
+            let uid = unistd::Uid::from_raw(user.uid);
+            unistd::setresgid(gid, gid, gid).expect("setresgid failed");
+            unistd::setresuid(uid, uid, uid).expect("setresuid failed");
+        }
 
-            if unsafe {
-                seccomp_sys::seccomp_rule_add(
-                    filters.context,
-                    action.to_seccomp_action(),
-                    syscall_id,
-                    0,
-                )
-            } < 0
-            {
-                panic!("seccomp_rule_add failed");
+        if unsafe { libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) } != 0 {
+            panic!("prctl failed: {}", nix::errno::Errno::last());
+        }
+    }
+
+    // kept open for the program, unlike everything else above stderr
+    pub fn pass_fd(mut self, fd: i32) -> Self {
+        self.pass_fds.push(fd);
+        self
+    }
+
+    fn close_inherited_fds(&self) {
+        let mut pass_fds: Vec<u32> = self
+            .pass_fds
+            .iter()
+            .filter(|fd| **fd > 2)
+            .map(|fd| *fd as u32)
+            .collect();
+        pass_fds.sort_unstable();
+        pass_fds.dedup();
+
+        // marked rather than closed, as the supervisor channel is still needed until exec
+        let mut first = 3;
+        for fd in &pass_fds {
+            close_on_exec(first, *fd - 1);
+            first = fd + 1;
+        }
+        close_on_exec(first, u32::MAX);
+
+        for fd in &pass_fds {
+            if unsafe { libc::fcntl(*fd as i32, libc::F_SETFD, 0) } != 0 {
+                panic!(
+                    "passing file descriptor {} failed: {}",
+                    fd,
+                    nix::errno::Errno::last()
+                );
             }
-        });
+        }
+    }
 
-        filters.load();
+    pub fn syscall_filter(mut self, filter: SyscallFilter) -> Self {
+        self.syscall_filters = Some(filter);
+        self
+    }
+
+    pub fn trace(mut self) -> Self {
+        self.traced = true;
+        self
+    }
+
+    // the write end of a pipe the parent learns what Landlock enforced from
+    pub fn landlock_status(mut self, fd: i32) -> Self {
+        self.landlock_status = Some(fd);
+        self
+    }
+
+    // the parent answers the notifications of `filter` through `channel`
+    pub fn supervise(mut self, filter: SyscallFilter, channel: i32) -> Self {
+        self.supervisor = Some((filter, channel));
+        self
+    }
+
+    // returns the path to exec the program with, the one the filter allows
+    fn apply_syscall_filter(&self, pid_namespace: bool) -> &CStr {
+        // loaded first so that sending its listener is not up to the profile
+        if let Some((filter, channel)) = &self.supervisor {
+            send_listener(*channel, filter.load_with_listener());
+        }
+        let path = hidden_copy(&self.path);
+        let mut filter = self.syscall_filters.clone().unwrap();
+        filter.allow_exec(path.as_ptr());
+        // a PID namespace already keeps the program's signals to itself and the sandbox's PID 1,
+        // which ignores them, otherwise it keeps its pid across the exec
+        if !pid_namespace {
+            filter.confine_signals(unistd::getpid().as_raw());
+        }
+        filter.load();
+        path
     }
 
     pub fn stdin(mut self, file_path: String) -> Self {
@@ -154,24 +408,77 @@ impl Process {
     }
 
     pub fn stdout(mut self, file_path: String) -> Self {
-        let path = Some(CString::new(file_path).unwrap());
+        let path = CString::new(file_path).unwrap();
         self.stdout_fd = unsafe {
-            libc::creat(
-                path.as_ref().unwrap().as_ptr(),
+            libc::open(
+                path.as_ptr(),
+                libc::O_WRONLY | libc::O_CREAT | libc::O_TRUNC,
                 libc::S_IRUSR | libc::S_IWUSR,
-            );
-            libc::open(path.as_ref().unwrap().as_ptr(), libc::O_WRONLY, 0)
+            )
         };
         self
     }
 
+    pub fn stderr(mut self, file_path: String) -> Self {
+        let path = CString::new(file_path).unwrap();
+        self.stderr_fd = unsafe {
+            libc::open(
+                path.as_ptr(),
+                libc::O_WRONLY | libc::O_CREAT | libc::O_TRUNC,
+                libc::S_IRUSR | libc::S_IWUSR,
+            )
+        };
+        self
+    }
+
+    // the read end of a pipe the parent writes
+    pub fn stdin_pipe(mut self, fd: i32) -> Self {
+        self.stdin_fd = fd;
+        self
+    }
+
+    // the write end of a pipe the parent reads
+    pub fn stdout_pipe(mut self, fd: i32) -> Self {
+        self.stdout_fd = fd;
+        self
+    }
+
+    // the write end of a pipe the parent reads
+    pub fn stderr_pipe(mut self, fd: i32) -> Self {
+        self.stderr_fd = fd;
+        self
+    }
+
     pub fn run(&self) -> i32 {
-        self.setrlimit();
-        self.chroot();
-        if self.syscall_filters.is_some() {
-            self.apply_syscall_filter();
+        if self.traced {
+            // wait for the tracer to set its options, which also cover the namespace's processes
+            ptrace::traceme().expect("ptrace failed");
+            unsafe { libc::raise(libc::SIGSTOP) };
         }
 
+        // while /proc is still the host's, for kernels without close_range
+        self.close_inherited_fds();
+
+        let namespaces = match &self.dir {
+            Some(dir) => dir.namespaces(self.namespaces.clone()),
+            None => self.namespaces.clone(),
+        };
+        let pid_namespace = namespaces.as_ref().is_some_and(|namespaces| namespaces.pid);
+        if let Some(namespaces) = namespaces {
+            let root_dir = self.dir.as_ref().and_then(|dir| dir.root_dir.as_deref());
+            if let Some(status) = namespaces.enter(root_dir) {
+                namespace::exit_with(status);
+            }
+        }
+        // the sandbox's own processes around the program stay out of the count
+        if let Some(path) = &self.pids_cgroup {
+            cgroup::join(path);
+        }
+
+        // before the limits, which would also hold back an overlay's archiving
+        self.switch_root();
+        self.setrlimit();
+
         if self.stdin_fd != 0 {
             unsafe { libc::dup2(self.stdin_fd, 0) };
         }
@@ -180,15 +487,99 @@ impl Process {
             unsafe { libc::dup2(self.stdout_fd, 1) };
         }
 
-        match nix::unistd::execv(&self.path, self.args.as_ref()) {
-            Ok(_) => {
-                println!("execve ok");
-                0
+        if self.stderr_fd != 2 {
+            unsafe { libc::dup2(self.stderr_fd, 2) };
+        }
+
+        self.drop_privileges();
+        if let Some(directory) = self.dir.as_ref().filter(|dir| dir.landlock) {
+            let path = Path::new(self.path.to_str().unwrap());
+            let status = landlock::restrict(directory, path);
+            if let Some(fd) = self.landlock_status {
+                landlock::send_status(fd, &status);
             }
-            Err(e) => {
-                println!("execve failed: {}", e);
-                50000
+        }
+
+        let path = match self.syscall_filters {
+            Some(_) => self.apply_syscall_filter(pid_namespace),
+            None => &self.path,
+        };
+
+        // execv only returns on failure, which goes where the program's errors would
+        let Err(e) = nix::unistd::execv(path, self.args.as_ref());
+        eprintln!("execve failed: {}", e);
+        50000
+    }
+}
+
+// a copy of path at a random address, which the program cannot learn and so cannot pass to an
+// exec of its own, as a wrong guess is a violation
+fn hidden_copy(path: &CStr) -> &'static CStr {
+    let bytes = path.to_bytes_with_nul();
+    let page = 4096;
+    let size = (bytes.len() + 2 * page - 1) / page * page;
+    loop {
+        let mut random = [0u64; 2];
+        let len = std::mem::size_of_val(&random);
+        if unsafe { libc::getrandom(random.as_mut_ptr().cast(), len, 0) } != len as isize {
+            panic!("getrandom failed");
+        }
+        // anywhere between 1 TiB and the top of the 47 bit address space
+        let start = 1 << 40;
+        let end = (1 << 47) - size as u64;
+        let address = (start + random[0] % (end - start)) & !(page as u64 - 1);
+        let mapped = unsafe {
+            libc::mmap(
+                address as *mut libc::c_void,
+                size,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS | libc::MAP_FIXED_NOREPLACE,
+                -1,
+                0,
+            )
+        };
+        if mapped == libc::MAP_FAILED {
+            match nix::errno::Errno::last() {
+                nix::errno::Errno::EEXIST => continue,
+                errno => panic!("mapping the program's path failed: {}", errno),
             }
         }
+        // kernels before 4.17 take the address as a hint only
+        if mapped as u64 != address {
+            unsafe { libc::munmap(mapped, size) };
+            continue;
+        }
+
+        let offset = (random[1] % (size - bytes.len()) as u64) as usize;
+        unsafe {
+            let copy = (mapped as *mut u8).add(offset);
+            std::ptr::copy_nonoverlapping(bytes.as_ptr(), copy, bytes.len());
+            return CStr::from_ptr(copy.cast());
+        }
+    }
+}
+
+fn close_on_exec(first: u32, last: u32) {
+    if first > last {
+        return;
+    }
+    let ret = unsafe {
+        libc::syscall(
+            libc::SYS_close_range,
+            first,
+            last,
+            libc::CLOSE_RANGE_CLOEXEC,
+        )
+    };
+    if ret == 0 {
+        return;
+    }
+
+    // close_range can only mark descriptors since Linux 5.11
+    let entries = fs::read_dir("/proc/self/fd").expect("listing file descriptors failed");
+    for fd in entries.filter_map(|entry| entry.ok()?.file_name().to_str()?.parse::<u32>().ok()) {
+        if (first..=last).contains(&fd) {
+            unsafe { libc::fcntl(fd as i32, libc::F_SETFD, libc::FD_CLOEXEC) };
+        }
     }
 }
@@ -0,0 +1,49 @@
+use std::{collections::BTreeMap, fs};
+
+use serde::Serialize;
+
+use crate::{
+    executor::ResourceUsage, judge::ResultKind, landlock::LandlockStatus, pipe::CapturedOutput,
+};
+
+#[derive(Debug, Serialize)]
+pub struct RunReport {
+    pub status: i32,
+    pub user_time_ms: u64,
+    pub cpu_time_ms: u64,
+    pub memory_kb: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<ResultKind>,
+    // system call names with how often they were made
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub syscalls: Option<BTreeMap<String, u64>>,
+    // what the program ran under, only when Landlock was asked for
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub landlock: Option<LandlockStatus>,
+    // what the program wrote to stdout and stderr, when they were captured
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stdout: Option<CapturedOutput>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stderr: Option<CapturedOutput>,
+}
+
+impl RunReport {
+    pub fn new(status: i32, usage: &ResourceUsage) -> Self {
+        RunReport {
+            status,
+            user_time_ms: usage.user_time.as_millis() as u64,
+            cpu_time_ms: usage.cpu_time.as_millis() as u64,
+            memory_kb: usage.memory,
+            result: None,
+            syscalls: None,
+            landlock: None,
+            stdout: None,
+            stderr: None,
+        }
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        let json = serde_json::to_string_pretty(self).map_err(|err| err.to_string())?;
+        fs::write(path, json).map_err(|err| format!("failed to write {}: {}", path, err))
+    }
+}
@@ -1,115 +1,588 @@
-use std::ptr;
+mod arch;
+mod bpf;
+#[cfg(all(test, feature = "libseccomp"))]
+mod libseccomp;
+mod syscalls;
 
-#[derive(Debug, Clone)]
+use std::{fmt, fs, path::Path, str::FromStr};
+
+use nix::libc;
+use serde::{Deserialize, Serialize};
+
+use arch::Arch;
+
+const SECCOMP_RET_KILL_PROCESS: u32 = 0x80000000;
+const SECCOMP_RET_KILL_THREAD: u32 = 0x00000000;
+const SECCOMP_RET_TRAP: u32 = 0x00030000;
+const SECCOMP_RET_ERRNO: u32 = 0x00050000;
+const SECCOMP_RET_USER_NOTIF: u32 = 0x7fc00000;
+const SECCOMP_RET_TRACE: u32 = 0x7ff00000;
+const SECCOMP_RET_LOG: u32 = 0x7ffc0000;
+const SECCOMP_RET_ALLOW: u32 = 0x7fff0000;
+const SECCOMP_RET_DATA: u32 = 0x0000ffff;
+
+// arguments whose observed values are kept when a profile is learned
+const ARGUMENT_SHAPES: [(&str, u32); 8] = [
+    ("clone", 0),
+    ("fcntl", 1),
+    ("ioctl", 1),
+    ("open", 1),
+    ("openat", 2),
+    ("prctl", 0),
+    ("prlimit64", 0),
+    ("socket", 0),
+];
+
+pub fn argument_shape(syscall: &str) -> Option<u32> {
+    ARGUMENT_SHAPES
+        .iter()
+        .find(|(name, _)| *name == syscall)
+        .map(|(_, index)| *index)
+}
+
+pub fn arch_name(arch: u32) -> Option<&'static str> {
+    Arch::from_token(arch).map(|arch| arch.name)
+}
+
+pub fn syscall_name(arch: u32, number: i32) -> Option<String> {
+    Arch::from_token(arch)?
+        .syscall_name(number)
+        .map(str::to_string)
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum SyscallFilterAction {
     Allow,
-    Kill,
-    Err(u32),
+    KillThread,
+    KillProcess,
+    Errno(u32),
+    Trap,
+    Log,
+    Trace(u32),
+    Notify,
 }
 
 impl SyscallFilterAction {
     pub fn to_seccomp_action(&self) -> u32 {
         match self {
-            SyscallFilterAction::Allow => seccomp_sys::SCMP_ACT_ALLOW,
-            SyscallFilterAction::Kill => seccomp_sys::SCMP_ACT_KILL,
-            SyscallFilterAction::Err(errno) => errno.clone(),
+            SyscallFilterAction::Allow => SECCOMP_RET_ALLOW,
+            SyscallFilterAction::KillThread => SECCOMP_RET_KILL_THREAD,
+            SyscallFilterAction::KillProcess => SECCOMP_RET_KILL_PROCESS,
+            SyscallFilterAction::Errno(errno) => SECCOMP_RET_ERRNO | (errno & SECCOMP_RET_DATA),
+            SyscallFilterAction::Trap => SECCOMP_RET_TRAP,
+            SyscallFilterAction::Log => SECCOMP_RET_LOG,
+            SyscallFilterAction::Trace(value) => SECCOMP_RET_TRACE | (value & SECCOMP_RET_DATA),
+            SyscallFilterAction::Notify => SECCOMP_RET_USER_NOTIF,
+        }
+    }
+}
+
+impl fmt::Display for SyscallFilterAction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SyscallFilterAction::Allow => write!(f, "allow"),
+            SyscallFilterAction::KillThread => write!(f, "kill_thread"),
+            SyscallFilterAction::KillProcess => write!(f, "kill_process"),
+            SyscallFilterAction::Errno(errno) => write!(f, "errno:{}", errno),
+            SyscallFilterAction::Trap => write!(f, "trap"),
+            SyscallFilterAction::Log => write!(f, "log"),
+            SyscallFilterAction::Trace(value) => write!(f, "trace:{}", value),
+            SyscallFilterAction::Notify => write!(f, "notify"),
         }
     }
 }
 
+impl FromStr for SyscallFilterAction {
+    type Err = String;
+
+    // "errno" and "trace" take their value after a colon, e.g. "errno:1"
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, value) = match s.split_once(':') {
+            Some((name, value)) => match value.parse::<u32>() {
+                Ok(value) if value <= 0xffff => (name, Some(value)),
+                _ => return Err(format!("invalid syscall action value: {}", s)),
+            },
+            None => (s, None),
+        };
+
+        match (name, value) {
+            ("allow", None) => Ok(SyscallFilterAction::Allow),
+            ("kill_thread", None) => Ok(SyscallFilterAction::KillThread),
+            ("kill" | "kill_process", None) => Ok(SyscallFilterAction::KillProcess),
+            ("errno", value) => Ok(SyscallFilterAction::Errno(
+                value.unwrap_or(libc::EPERM as u32),
+            )),
+            ("trap", None) => Ok(SyscallFilterAction::Trap),
+            ("log", None) => Ok(SyscallFilterAction::Log),
+            ("trace", value) => Ok(SyscallFilterAction::Trace(value.unwrap_or(0))),
+            ("notify", None) => Ok(SyscallFilterAction::Notify),
+            _ => Err(format!("invalid syscall action: {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum SyscallArgCompare {
+    Equal(u64),
+    NotEqual(u64),
+    Less(u64),
+    LessOrEqual(u64),
+    Greater(u64),
+    GreaterOrEqual(u64),
+    MaskedEqual { mask: u64, value: u64 },
+    Range { min: u64, max: u64 },
+}
+
+#[derive(Debug, Clone)]
+pub struct SyscallArg {
+    pub index: u32,
+    pub compare: SyscallArgCompare,
+}
+
+impl SyscallArg {
+    pub fn new(index: u32, compare: SyscallArgCompare) -> Self {
+        SyscallArg { index, compare }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SyscallRule {
+    pub name: String,
+    pub action: SyscallFilterAction,
+    pub args: Vec<SyscallArg>,
+}
+
 #[derive(Debug, Clone)]
 pub struct SyscallFilter {
     pub default_action: SyscallFilterAction,
-    pub rules: Vec<(String, SyscallFilterAction)>,
-    pub context: *mut seccomp_sys::scmp_filter_ctx,
+    pub rules: Vec<SyscallRule>,
+    pub architectures: Vec<String>,
+    // taken by calls from any other ABI, e.g. int 0x80 or x32 calls on x86_64
+    pub bad_arch_action: SyscallFilterAction,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ProfileFile {
+    default_action: Option<String>,
+    #[serde(default)]
+    architectures: Vec<String>,
+    bad_arch_action: Option<String>,
+    #[serde(default)]
+    syscalls: Vec<ProfileRule>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ProfileRule {
+    names: Vec<String>,
+    action: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    args: Vec<ProfileArg>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ProfileArg {
+    index: u32,
+    op: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    value: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    mask: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    min: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max: Option<u64>,
+}
+
+impl ProfileArg {
+    fn from_syscall_arg(arg: &SyscallArg) -> Self {
+        let mut profile_arg = ProfileArg {
+            index: arg.index,
+            op: String::new(),
+            value: None,
+            mask: None,
+            min: None,
+            max: None,
+        };
+
+        let (op, value) = match arg.compare {
+            SyscallArgCompare::Equal(value) => ("eq", value),
+            SyscallArgCompare::NotEqual(value) => ("ne", value),
+            SyscallArgCompare::Less(value) => ("lt", value),
+            SyscallArgCompare::LessOrEqual(value) => ("le", value),
+            SyscallArgCompare::Greater(value) => ("gt", value),
+            SyscallArgCompare::GreaterOrEqual(value) => ("ge", value),
+            SyscallArgCompare::MaskedEqual { mask, value } => {
+                profile_arg.mask = Some(mask);
+                ("masked_eq", value)
+            }
+            SyscallArgCompare::Range { min, max } => {
+                profile_arg.op = "range".to_string();
+                profile_arg.min = Some(min);
+                profile_arg.max = Some(max);
+                return profile_arg;
+            }
+        };
+        profile_arg.op = op.to_string();
+        profile_arg.value = Some(value);
+        profile_arg
+    }
+
+    fn to_syscall_arg(&self) -> Result<SyscallArg, String> {
+        if self.index > 5 {
+            return Err(format!("invalid argument index: {}", self.index));
+        }
+
+        let value = || {
+            self.value
+                .ok_or_else(|| format!("missing value for {} comparison", self.op))
+        };
+        let compare = match self.op.as_str() {
+            "eq" => SyscallArgCompare::Equal(value()?),
+            "ne" => SyscallArgCompare::NotEqual(value()?),
+            "lt" => SyscallArgCompare::Less(value()?),
+            "le" => SyscallArgCompare::LessOrEqual(value()?),
+            "gt" => SyscallArgCompare::Greater(value()?),
+            "ge" => SyscallArgCompare::GreaterOrEqual(value()?),
+            "masked_eq" => match self.mask {
+                Some(mask) => SyscallArgCompare::MaskedEqual {
+                    mask,
+                    value: value()?,
+                },
+                None => return Err("missing mask for masked_eq comparison".to_string()),
+            },
+            "range" => match (self.min, self.max) {
+                (Some(min), Some(max)) if min <= max => SyscallArgCompare::Range { min, max },
+                _ => return Err("range comparison needs min <= max".to_string()),
+            },
+            op => return Err(format!("invalid argument comparison: {}", op)),
+        };
+
+        Ok(SyscallArg::new(self.index, compare))
+    }
 }
 
 impl Default for SyscallFilter {
     fn default() -> Self {
+        SyscallFilter::new(SyscallFilterAction::KillProcess)
+    }
+}
+
+// system calls glibc needs to start, allocate, do stdio and exit, the program's own execve
+// being allowed by Process alone
+const NATIVE_CALLS: [&str; 58] = [
+    "access",
+    "arch_prctl",
+    "brk",
+    "clock_getres",
+    "clock_gettime",
+    "clock_nanosleep",
+    "close",
+    "dup",
+    "dup2",
+    "dup3",
+    "exit",
+    "exit_group",
+    "faccessat",
+    "faccessat2",
+    "fcntl",
+    "fstat",
+    "futex",
+    "getcwd",
+    "getegid",
+    "geteuid",
+    "getgid",
+    "getpid",
+    "getppid",
+    "getrandom",
+    "getrlimit",
+    "gettid",
+    "gettimeofday",
+    "getuid",
+    "lseek",
+    "lstat",
+    "madvise",
+    "mmap",
+    "mprotect",
+    "mremap",
+    "munmap",
+    "nanosleep",
+    "newfstatat",
+    "pread64",
+    "pwrite64",
+    "read",
+    "readlink",
+    "readlinkat",
+    "readv",
+    "rseq",
+    "rt_sigaction",
+    "rt_sigprocmask",
+    "rt_sigreturn",
+    "sched_getaffinity",
+    "sched_yield",
+    "set_robust_list",
+    "set_tid_address",
+    "sigaltstack",
+    "stat",
+    "statx",
+    "time",
+    "uname",
+    "write",
+    "writev",
+];
+
+// managed runtimes such as the JVM start threads, poll and inspect themselves
+const RUNTIME_CALLS: [&str; 43] = [
+    "epoll_create1",
+    "epoll_ctl",
+    "epoll_pwait",
+    "epoll_wait",
+    "eventfd2",
+    "fchdir",
+    "fdatasync",
+    "flock",
+    "fstatfs",
+    "fsync",
+    "ftruncate",
+    "get_robust_list",
+    "getdents",
+    "getdents64",
+    "getitimer",
+    "getpriority",
+    "getrusage",
+    "getsid",
+    "membarrier",
+    "mincore",
+    "pipe",
+    "pipe2",
+    "poll",
+    "ppoll",
+    "prctl",
+    "pselect6",
+    "rt_sigqueueinfo",
+    "rt_sigtimedwait",
+    "sched_getparam",
+    "sched_getscheduler",
+    "sched_setaffinity",
+    "select",
+    "setitimer",
+    "statfs",
+    "sysinfo",
+    "timer_create",
+    "timer_delete",
+    "timer_settime",
+    "times",
+    "tkill",
+    "umask",
+    "unlink",
+    "wait4",
+];
+
+// allowed with argument conditions in the run profiles
+const RESTRICTED_CALLS: [&str; 7] = [
+    "clone",
+    "clone3",
+    "ioctl",
+    "open",
+    "openat",
+    "prlimit64",
+    "tgkill",
+];
+
+// calls whose first argument names the process that is signalled
+const SIGNAL_CALLS: [&str; 5] = [
+    "kill",
+    "rt_sigqueueinfo",
+    "rt_tgsigqueueinfo",
+    "tgkill",
+    "tkill",
+];
+
+// compiler drivers spawn cc1/as/ld and write objects and temporary files
+const COMPILER_CALLS: [&str; 20] = [
+    "chdir",
+    "chmod",
+    "creat",
+    "execve",
+    "fchmod",
+    "fork",
+    "getpgrp",
+    "kill",
+    "mkdir",
+    "mkdirat",
+    "rename",
+    "renameat",
+    "renameat2",
+    "rmdir",
+    "setpgid",
+    "symlink",
+    "unlinkat",
+    "utimensat",
+    "vfork",
+    "waitid",
+];
+
+impl SyscallFilter {
+    pub fn new(default_action: SyscallFilterAction) -> Self {
         SyscallFilter {
-            default_action: SyscallFilterAction::Kill,
+            default_action,
             rules: vec![],
-            context: ptr::null_mut(),
+            architectures: vec![],
+            bad_arch_action: SyscallFilterAction::KillProcess,
         }
     }
-}
 
-impl Drop for SyscallFilter {
-    fn drop(&mut self) {
-        unsafe {
-            seccomp_sys::seccomp_release(self.context);
+    pub fn native() -> Self {
+        let mut filter = SyscallFilter::with_allowed_calls(&[&NATIVE_CALLS]);
+        filter.add_restricted_calls();
+        filter
+    }
+
+    pub fn runtime() -> Self {
+        let mut filter = SyscallFilter::with_allowed_calls(&[&NATIVE_CALLS, &RUNTIME_CALLS]);
+        filter.add_restricted_calls();
+        filter.add_with_args(
+            "socket",
+            SyscallFilterAction::Allow,
+            vec![SyscallArg::new(
+                0,
+                SyscallArgCompare::Equal(libc::AF_UNIX as u64),
+            )],
+        );
+        filter
+    }
+
+    pub fn compile() -> Self {
+        SyscallFilter::with_allowed_calls(&[
+            &NATIVE_CALLS,
+            &RUNTIME_CALLS,
+            &COMPILER_CALLS,
+            &RESTRICTED_CALLS,
+        ])
+    }
+
+    pub fn profile(name: &str) -> Option<Self> {
+        match name {
+            "native" => Some(SyscallFilter::native()),
+            "runtime" => Some(SyscallFilter::runtime()),
+            "compile" => Some(SyscallFilter::compile()),
+            _ => None,
         }
     }
-}
 
-impl SyscallFilter {
-    pub fn new() -> Self {
-        let mut filter = SyscallFilter::default();
-
-        filter.default_action(SyscallFilterAction::Allow);
-
-        filter.context =
-            unsafe { seccomp_sys::seccomp_init(filter.default_action.to_seccomp_action()) };
-
-        let denied_calls = vec![
-            "_sysctl",
-            "acct",
-            "add_key",
-            "bpf",
-            "chroot",
-            "clock_adjtime",
-            "clock_settime",
-            "clone",
-            "connect",
-            "create_module",
-            "delete_module",
-            "finit_module",
-            "fork",
-            "get_kernel_syms",
-            "get_mempolicy",
-            "getdents",
-            "getdents64",
-            "init_module",
-            "ioperm",
-            "iopl",
-            "kcmp",
-            "kexec_file_load",
-            "kexec_load",
-            "keyctl",
-            "lookup_dcookie",
-            "mbind",
-            "mount",
-            "move_pages",
-            "name_to_handle_at",
-            "nfsservctl",
-            "open_by_handle_at",
-            "perf_event_open",
-            "personality",
-            "pivot_root",
-            "process_vm_readv",
-            "process_vm_writev",
-            "ptrace",
-            "query_module",
-            "quotactl",
-            "reboot",
-            "request_key",
-            "set_mempolicy",
-            "setns",
-            "setrlimit",
-            "settimeofday",
-            "swapoff",
-            "swapon",
-            "sysfs",
-            "umount2",
-            "unshare",
-            "uselib",
-            "userfaultfd",
-            "ustat",
-            "vfork",
-        ];
-
-        for syscall_name in denied_calls {
-            filter.add(syscall_name, SyscallFilterAction::Kill);
+    // a builtin profile name or the path of a TOML or JSON profile file
+    pub fn resolve(profile: &str) -> Result<Self, String> {
+        match SyscallFilter::profile(profile) {
+            Some(filter) => Ok(filter),
+            None => SyscallFilter::from_file(Path::new(profile)),
+        }
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self, String> {
+        let content =
+            fs::read_to_string(path).map_err(|err| format!("{}: {}", path.display(), err))?;
+        let file: ProfileFile = if path.extension() == Some("json".as_ref()) {
+            serde_json::from_str(&content).map_err(|err| format!("{}: {}", path.display(), err))?
+        } else {
+            toml::from_str(&content).map_err(|err| format!("{}: {}", path.display(), err))?
+        };
+
+        let default_action = match &file.default_action {
+            Some(action) => action.parse()?,
+            None => SyscallFilterAction::KillProcess,
+        };
+        let mut filter = SyscallFilter::new(default_action);
+        filter.architectures = file.architectures;
+        if let Some(action) = &file.bad_arch_action {
+            filter.bad_arch_action = action.parse()?;
+        }
+
+        for rule in &file.syscalls {
+            let action: SyscallFilterAction = rule.action.parse()?;
+            let args = rule
+                .args
+                .iter()
+                .map(ProfileArg::to_syscall_arg)
+                .collect::<Result<Vec<_>, _>>()?;
+
+            for name in &rule.names {
+                filter.add_with_args(name, action.clone(), args.clone());
+            }
+        }
+
+        Ok(filter)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let mut file = ProfileFile {
+            default_action: Some(self.default_action.to_string()),
+            architectures: self.architectures.clone(),
+            bad_arch_action: Some(self.bad_arch_action.to_string()),
+            syscalls: vec![],
+        };
+
+        // unconditional rules with the same action share one entry
+        for rule in &self.rules {
+            let action = rule.action.to_string();
+            match file.syscalls.last_mut() {
+                Some(last)
+                    if rule.args.is_empty() && last.args.is_empty() && last.action == action =>
+                {
+                    last.names.push(rule.name.clone())
+                }
+                _ => file.syscalls.push(ProfileRule {
+                    names: vec![rule.name.clone()],
+                    action,
+                    args: rule.args.iter().map(ProfileArg::from_syscall_arg).collect(),
+                }),
+            }
+        }
+
+        let content = if path.extension() == Some("json".as_ref()) {
+            serde_json::to_string_pretty(&file).map_err(|err| err.to_string())?
+        } else {
+            toml::to_string(&file).map_err(|err| err.to_string())?
+        };
+        fs::write(path, content).map_err(|err| format!("{}: {}", path.display(), err))
+    }
+
+    fn arches(&self) -> Result<Vec<&'static Arch>, String> {
+        if self.architectures.is_empty() {
+            return Ok(vec![Arch::native()]);
+        }
+
+        self.architectures
+            .iter()
+            .map(|name| {
+                Arch::from_name(name).ok_or_else(|| format!("unknown architecture: {}", name))
+            })
+            .collect()
+    }
+
+    // catches what load() would otherwise only find out inside the child
+    pub fn validate(&self) -> Result<(), String> {
+        let arches = self.arches()?;
+
+        for rule in &self.rules {
+            if !arches.iter().any(|arch| arch.resolve(&rule.name).is_some()) {
+                return Err(format!("unknown system call: {}", rule.name));
+            }
+        }
+
+        bpf::compile(self, &arches).map(|_| ())
+    }
+
+    fn with_allowed_calls(allowed_calls: &[&[&str]]) -> Self {
+        let mut filter = SyscallFilter::new(SyscallFilterAction::KillProcess);
+
+        let mut allowed_calls = allowed_calls.concat();
+        allowed_calls.sort_unstable();
+        allowed_calls.dedup();
+
+        for syscall_name in allowed_calls {
+            filter.add(syscall_name, SyscallFilterAction::Allow);
         }
 
         filter
@@ -120,14 +593,158 @@ impl SyscallFilter {
         self
     }
 
+    // read-only opens, thread-creating clones, terminal queries and the program's own limits and
+    // signals only
+    fn add_restricted_calls(&mut self) {
+        let read_only = SyscallArgCompare::MaskedEqual {
+            mask: (libc::O_ACCMODE | libc::O_CREAT | libc::O_TRUNC) as u64,
+            value: libc::O_RDONLY as u64,
+        };
+        let thread = SyscallArgCompare::MaskedEqual {
+            mask: libc::CLONE_THREAD as u64,
+            value: libc::CLONE_THREAD as u64,
+        };
+
+        self.add_with_args(
+            "open",
+            SyscallFilterAction::Allow,
+            vec![SyscallArg::new(1, read_only.clone())],
+        )
+        .add_with_args(
+            "openat",
+            SyscallFilterAction::Allow,
+            vec![SyscallArg::new(2, read_only)],
+        )
+        .add_with_args(
+            "clone",
+            SyscallFilterAction::Allow,
+            vec![SyscallArg::new(0, thread)],
+        )
+        // clone3 passes its flags in memory, so make glibc fall back to clone
+        .add("clone3", SyscallFilterAction::Errno(libc::ENOSYS as u32))
+        // 0 is the calling process
+        .add_with_args(
+            "prlimit64",
+            SyscallFilterAction::Allow,
+            vec![SyscallArg::new(0, SyscallArgCompare::Equal(0))],
+        )
+        // its target is only known once the program runs, see confine_signals
+        .add("tgkill", SyscallFilterAction::Allow);
+
+        let requests: [u64; 2] = [libc::TCGETS, libc::FIONREAD];
+        for request in requests {
+            self.add_with_args(
+                "ioctl",
+                SyscallFilterAction::Allow,
+                vec![SyscallArg::new(1, SyscallArgCompare::Equal(request))],
+            );
+        }
+    }
+
+    // kills become traps so that a tracing parent can see the offending call
+    pub fn trap_violations(&mut self) -> &mut Self {
+        let is_kill = |action: &SyscallFilterAction| {
+            matches!(
+                action,
+                SyscallFilterAction::KillThread | SyscallFilterAction::KillProcess
+            )
+        };
+
+        if is_kill(&self.default_action) {
+            self.default_action = SyscallFilterAction::Trap;
+        }
+        if is_kill(&self.bad_arch_action) {
+            self.bad_arch_action = SyscallFilterAction::Trap;
+        }
+        for rule in &mut self.rules {
+            if is_kill(&rule.action) {
+                rule.action = SyscallFilterAction::Trap;
+            }
+        }
+        self
+    }
+
+    // allowed calls stop at a tracer first so that it can count them
+    pub fn trace_allowed(&mut self) -> &mut Self {
+        let is_allow = |action: &SyscallFilterAction| {
+            matches!(
+                action,
+                SyscallFilterAction::Allow | SyscallFilterAction::Log
+            )
+        };
+
+        if is_allow(&self.default_action) {
+            self.default_action = SyscallFilterAction::Trace(0);
+        }
+        for rule in &mut self.rules {
+            if is_allow(&rule.action) {
+                rule.action = SyscallFilterAction::Trace(0);
+            }
+        }
+        self
+    }
+
     pub fn add(&mut self, syscall: &str, action: SyscallFilterAction) -> &mut Self {
-        self.rules.push((syscall.to_string(), action));
+        self.add_with_args(syscall, action, vec![])
+    }
+
+    pub fn add_with_args(
+        &mut self,
+        syscall: &str,
+        action: SyscallFilterAction,
+        args: Vec<SyscallArg>,
+    ) -> &mut Self {
+        self.rules.push(SyscallRule {
+            name: syscall.to_string(),
+            action,
+            args,
+        });
+        self
+    }
+
+    // the exec that passes this very pointer, which is the sandbox's own exec of the program when
+    // the path is kept at an address the program cannot know
+    pub fn allow_exec(&mut self, path: *const libc::c_char) -> &mut Self {
+        self.add_with_args(
+            "execve",
+            SyscallFilterAction::Allow,
+            vec![SyscallArg::new(0, SyscallArgCompare::Equal(path as u64))],
+        )
+    }
+
+    // allowed signals only reach the process with this pid, not the sandbox that runs it
+    pub fn confine_signals(&mut self, pid: libc::pid_t) -> &mut Self {
+        for rule in &mut self.rules {
+            let allowed = matches!(
+                rule.action,
+                SyscallFilterAction::Allow | SyscallFilterAction::Log
+            );
+            if allowed && SIGNAL_CALLS.contains(&rule.name.as_str()) {
+                rule.args
+                    .push(SyscallArg::new(0, SyscallArgCompare::Equal(pid as u64)));
+            }
+        }
         self
     }
 
     pub fn load(&self) {
-        unsafe {
-            seccomp_sys::seccomp_load(self.context);
+        self.install(0);
+    }
+
+    // notify actions reach the returned listener instead of failing with ENOSYS
+    pub fn load_with_listener(&self) -> i32 {
+        self.install(libc::SECCOMP_FILTER_FLAG_NEW_LISTENER)
+    }
+
+    fn install(&self, flags: libc::c_ulong) -> i32 {
+        let program = self
+            .arches()
+            .and_then(|arches| bpf::compile(self, &arches))
+            .unwrap_or_else(|err| panic!("invalid seccomp filter: {}", err));
+
+        match bpf::install(&program, flags) {
+            Ok(ret) => ret,
+            Err(err) => panic!("{}", err),
         }
     }
 }
@@ -0,0 +1,79 @@
+use super::syscalls;
+
+pub const X32_SYSCALL_BIT: u32 = 0x40000000;
+
+pub struct Arch {
+    pub name: &'static str,
+    // libseccomp's architecture token, which tells x32 apart from x86_64
+    pub token: u32,
+    // the architecture the kernel reports in seccomp_data
+    pub audit_arch: u32,
+    pub wide_args: bool,
+    pub syscalls: &'static [(&'static str, i32)],
+}
+
+pub const ARCHES: [Arch; 4] = [
+    Arch {
+        name: "x86_64",
+        token: 0xc000003e,
+        audit_arch: 0xc000003e,
+        wide_args: true,
+        syscalls: syscalls::X86_64,
+    },
+    Arch {
+        name: "x86",
+        token: 0x40000003,
+        audit_arch: 0x40000003,
+        wide_args: false,
+        syscalls: syscalls::X86,
+    },
+    Arch {
+        name: "x32",
+        token: 0x4000003e,
+        audit_arch: 0xc000003e,
+        wide_args: false,
+        syscalls: syscalls::X32,
+    },
+    Arch {
+        name: "aarch64",
+        token: 0xc00000b7,
+        audit_arch: 0xc00000b7,
+        wide_args: true,
+        syscalls: syscalls::AARCH64,
+    },
+];
+
+#[cfg(target_arch = "x86_64")]
+const NATIVE: &str = "x86_64";
+#[cfg(target_arch = "x86")]
+const NATIVE: &str = "x86";
+#[cfg(target_arch = "aarch64")]
+const NATIVE: &str = "aarch64";
+
+impl Arch {
+    pub fn native() -> &'static Arch {
+        Arch::from_name(NATIVE).unwrap()
+    }
+
+    pub fn from_name(name: &str) -> Option<&'static Arch> {
+        ARCHES.iter().find(|arch| arch.name == name)
+    }
+
+    pub fn from_token(token: u32) -> Option<&'static Arch> {
+        ARCHES.iter().find(|arch| arch.token == token)
+    }
+
+    pub fn resolve(&self, syscall: &str) -> Option<i32> {
+        self.syscalls
+            .iter()
+            .find(|(name, _)| *name == syscall)
+            .map(|(_, number)| *number)
+    }
+
+    pub fn syscall_name(&self, number: i32) -> Option<&'static str> {
+        self.syscalls
+            .iter()
+            .find(|(_, nr)| *nr == number)
+            .map(|(name, _)| *name)
+    }
+}
@@ -0,0 +1,304 @@
+use std::convert::TryFrom;
+
+use nix::libc;
+
+use super::{
+    arch::{Arch, X32_SYSCALL_BIT},
+    SyscallArg, SyscallArgCompare, SyscallFilter, SyscallRule,
+};
+
+const BPF_LD_W_ABS: u16 = 0x20;
+const BPF_ALU_AND_K: u16 = 0x54;
+const BPF_JMP_JA: u16 = 0x05;
+const BPF_JMP_JEQ_K: u16 = 0x15;
+const BPF_JMP_JGT_K: u16 = 0x25;
+const BPF_JMP_JGE_K: u16 = 0x35;
+const BPF_RET_K: u16 = 0x06;
+const BPF_MAXINSNS: usize = 4096;
+
+const SECCOMP_SET_MODE_FILTER: libc::c_ulong = 1;
+
+// offsets into struct seccomp_data
+const DATA_NR: u32 = 0;
+const DATA_ARCH: u32 = 4;
+const DATA_ARGS: u32 = 16;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SockFilter {
+    pub code: u16,
+    pub jt: u8,
+    pub jf: u8,
+    pub k: u32,
+}
+
+fn stmt(code: u16, k: u32) -> SockFilter {
+    SockFilter {
+        code,
+        jt: 0,
+        jf: 0,
+        k,
+    }
+}
+
+fn jump(code: u16, k: u32, jt: usize, jf: usize) -> SockFilter {
+    SockFilter {
+        code,
+        jt: u8::try_from(jt).expect("bpf jump out of range"),
+        jf: u8::try_from(jf).expect("bpf jump out of range"),
+        k,
+    }
+}
+
+// offsets of the high and low words of an argument
+fn arg_words(index: u32) -> (u32, u32) {
+    let offset = DATA_ARGS + index * 8;
+    if cfg!(target_endian = "little") {
+        (offset + 4, offset)
+    } else {
+        (offset, offset + 4)
+    }
+}
+
+// falls through when the argument matches and jumps `fail` instructions past its end otherwise
+fn compare(index: u32, compare: &SyscallArgCompare, wide: bool, fail: usize) -> Vec<SockFilter> {
+    let (hi, lo) = arg_words(index);
+    let split = |value: u64| ((value >> 32) as u32, value as u32);
+
+    if let SyscallArgCompare::Range { min, max } = compare {
+        let mut code = self::compare(index, &SyscallArgCompare::LessOrEqual(*max), wide, fail);
+        let mut lower = self::compare(
+            index,
+            &SyscallArgCompare::GreaterOrEqual(*min),
+            wide,
+            code.len() + fail,
+        );
+        lower.append(&mut code);
+        return lower;
+    }
+
+    // 32-bit ABIs only have the low word
+    if !wide {
+        let code = match *compare {
+            SyscallArgCompare::Equal(value) => jump(BPF_JMP_JEQ_K, value as u32, 0, fail),
+            SyscallArgCompare::NotEqual(value) => jump(BPF_JMP_JEQ_K, value as u32, fail, 0),
+            SyscallArgCompare::Less(value) => jump(BPF_JMP_JGE_K, value as u32, fail, 0),
+            SyscallArgCompare::LessOrEqual(value) => jump(BPF_JMP_JGT_K, value as u32, fail, 0),
+            SyscallArgCompare::Greater(value) => jump(BPF_JMP_JGT_K, value as u32, 0, fail),
+            SyscallArgCompare::GreaterOrEqual(value) => jump(BPF_JMP_JGE_K, value as u32, 0, fail),
+            SyscallArgCompare::MaskedEqual { mask, value } => {
+                return vec![
+                    stmt(BPF_LD_W_ABS, lo),
+                    stmt(BPF_ALU_AND_K, mask as u32),
+                    jump(BPF_JMP_JEQ_K, value as u32, 0, fail),
+                ]
+            }
+            SyscallArgCompare::Range { .. } => unreachable!(),
+        };
+        return vec![stmt(BPF_LD_W_ABS, lo), code];
+    }
+
+    match *compare {
+        SyscallArgCompare::Equal(value) => {
+            let (value_hi, value_lo) = split(value);
+            vec![
+                stmt(BPF_LD_W_ABS, hi),
+                jump(BPF_JMP_JEQ_K, value_hi, 0, fail + 2),
+                stmt(BPF_LD_W_ABS, lo),
+                jump(BPF_JMP_JEQ_K, value_lo, 0, fail),
+            ]
+        }
+        SyscallArgCompare::NotEqual(value) => {
+            let (value_hi, value_lo) = split(value);
+            vec![
+                stmt(BPF_LD_W_ABS, hi),
+                jump(BPF_JMP_JEQ_K, value_hi, 0, 2),
+                stmt(BPF_LD_W_ABS, lo),
+                jump(BPF_JMP_JEQ_K, value_lo, fail, 0),
+            ]
+        }
+        SyscallArgCompare::Greater(value) | SyscallArgCompare::GreaterOrEqual(value) => {
+            let (value_hi, value_lo) = split(value);
+            let low_code = match compare {
+                SyscallArgCompare::Greater(_) => BPF_JMP_JGT_K,
+                _ => BPF_JMP_JGE_K,
+            };
+            vec![
+                stmt(BPF_LD_W_ABS, hi),
+                jump(BPF_JMP_JGT_K, value_hi, 3, 0),
+                jump(BPF_JMP_JEQ_K, value_hi, 0, fail + 2),
+                stmt(BPF_LD_W_ABS, lo),
+                jump(low_code, value_lo, 0, fail),
+            ]
+        }
+        SyscallArgCompare::Less(value) | SyscallArgCompare::LessOrEqual(value) => {
+            let (value_hi, value_lo) = split(value);
+            let low_code = match compare {
+                SyscallArgCompare::Less(_) => BPF_JMP_JGE_K,
+                _ => BPF_JMP_JGT_K,
+            };
+            vec![
+                stmt(BPF_LD_W_ABS, hi),
+                jump(BPF_JMP_JGT_K, value_hi, fail + 3, 0),
+                jump(BPF_JMP_JEQ_K, value_hi, 0, 2),
+                stmt(BPF_LD_W_ABS, lo),
+                jump(low_code, value_lo, fail, 0),
+            ]
+        }
+        SyscallArgCompare::MaskedEqual { mask, value } => {
+            let (mask_hi, mask_lo) = split(mask);
+            let (value_hi, value_lo) = split(value);
+            vec![
+                stmt(BPF_LD_W_ABS, hi),
+                stmt(BPF_ALU_AND_K, mask_hi),
+                jump(BPF_JMP_JEQ_K, value_hi, 0, fail + 3),
+                stmt(BPF_LD_W_ABS, lo),
+                stmt(BPF_ALU_AND_K, mask_lo),
+                jump(BPF_JMP_JEQ_K, value_lo, 0, fail),
+            ]
+        }
+        SyscallArgCompare::Range { .. } => unreachable!(),
+    }
+}
+
+// returns the rule's action when every argument matches and falls through otherwise
+fn rule_code(rule: &SyscallRule, wide: bool) -> Vec<SockFilter> {
+    let mut code = vec![stmt(BPF_RET_K, rule.action.to_seccomp_action())];
+
+    for SyscallArg { index, compare } in rule.args.iter().rev() {
+        let mut check = self::compare(*index, compare, wide, code.len());
+        check.append(&mut code);
+        code = check;
+    }
+
+    code
+}
+
+// expects the system call number in the accumulator
+fn arch_code(filter: &SyscallFilter, arch: &Arch) -> Vec<SockFilter> {
+    // rules of one system call are tried in the order they were added
+    let mut syscalls: Vec<(i32, Vec<&SyscallRule>)> = vec![];
+    for rule in &filter.rules {
+        let number = match arch.resolve(&rule.name) {
+            Some(number) => number,
+            None => continue,
+        };
+        match syscalls.iter_mut().find(|(nr, _)| *nr == number) {
+            Some((_, rules)) => rules.push(rule),
+            None => syscalls.push((number, vec![rule])),
+        }
+    }
+
+    let default_action = filter.default_action.to_seccomp_action();
+    let mut code = vec![];
+    for (number, rules) in syscalls {
+        let mut body: Vec<SockFilter> = rules
+            .iter()
+            .flat_map(|rule| rule_code(rule, arch.wide_args))
+            .collect();
+        body.push(stmt(BPF_RET_K, default_action));
+
+        code.push(jump(BPF_JMP_JEQ_K, number as u32, 1, 0));
+        code.push(stmt(BPF_JMP_JA, body.len() as u32));
+        code.append(&mut body);
+    }
+    code.push(stmt(BPF_RET_K, default_action));
+
+    code
+}
+
+pub fn compile(filter: &SyscallFilter, arches: &[&Arch]) -> Result<Vec<SockFilter>, String> {
+    let bad_arch_action = filter.bad_arch_action.to_seccomp_action();
+    let arch_or_bad = |name: &str| -> Vec<SockFilter> {
+        match arches.iter().find(|arch| arch.name == name) {
+            Some(arch) => arch_code(filter, arch),
+            None => vec![stmt(BPF_RET_K, bad_arch_action)],
+        }
+    };
+
+    let mut audit_arches: Vec<u32> = vec![];
+    for arch in arches {
+        if !audit_arches.contains(&arch.audit_arch) {
+            audit_arches.push(arch.audit_arch);
+        }
+    }
+
+    let mut blocks = vec![];
+    for audit_arch in &audit_arches {
+        let mut block = vec![stmt(BPF_LD_W_ABS, DATA_NR)];
+
+        // x86_64 and x32 share an audit architecture, x32 numbers carry an extra bit
+        if *audit_arch == Arch::from_name("x86_64").unwrap().audit_arch {
+            let mut x32 = arch_or_bad("x32");
+            if arches.iter().any(|arch| arch.name == "x86_64") {
+                // -1 is left to the x86_64 rules
+                let mut x86_64 = arch_or_bad("x86_64");
+                block.push(jump(BPF_JMP_JGE_K, X32_SYSCALL_BIT, 0, 2));
+                block.push(jump(BPF_JMP_JEQ_K, u32::MAX, 1, 0));
+                block.push(stmt(BPF_JMP_JA, x86_64.len() as u32));
+                block.append(&mut x86_64);
+            } else {
+                block.push(jump(BPF_JMP_JGE_K, X32_SYSCALL_BIT, 1, 0));
+                block.push(stmt(BPF_RET_K, bad_arch_action));
+            }
+            block.append(&mut x32);
+        } else {
+            let arch = arches
+                .iter()
+                .find(|arch| arch.audit_arch == *audit_arch)
+                .unwrap();
+            block.append(&mut arch_code(filter, arch));
+        }
+
+        blocks.push(block);
+    }
+
+    // load the architecture, dispatch to its block or take the bad architecture action
+    let mut program = vec![stmt(BPF_LD_W_ABS, DATA_ARCH)];
+    let dispatch_len = 1 + audit_arches.len() * 2 + 1;
+    let mut block_start = dispatch_len;
+    for (i, audit_arch) in audit_arches.iter().enumerate() {
+        program.push(jump(BPF_JMP_JEQ_K, *audit_arch, 0, 1));
+        program.push(stmt(BPF_JMP_JA, (block_start - (i * 2 + 3)) as u32));
+        block_start += blocks[i].len();
+    }
+    program.push(stmt(BPF_RET_K, bad_arch_action));
+    for mut block in blocks {
+        program.append(&mut block);
+    }
+
+    if program.len() > BPF_MAXINSNS {
+        return Err(format!(
+            "seccomp filter has {} instructions, the kernel accepts {}",
+            program.len(),
+            BPF_MAXINSNS
+        ));
+    }
+
+    Ok(program)
+}
+
+// returns what the seccomp call returned, the listener descriptor when asked for one
+pub fn install(program: &[SockFilter], flags: libc::c_ulong) -> Result<i32, String> {
+    let prog = libc::sock_fprog {
+        len: program.len() as libc::c_ushort,
+        filter: program.as_ptr() as *mut libc::sock_filter,
+    };
+
+    if unsafe { libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) } != 0 {
+        return Err(format!("prctl failed: {}", nix::errno::Errno::last()));
+    }
+    let ret = unsafe {
+        libc::syscall(
+            libc::SYS_seccomp,
+            SECCOMP_SET_MODE_FILTER,
+            flags,
+            &prog as *const libc::sock_fprog,
+        )
+    };
+    if ret < 0 {
+        return Err(format!("seccomp failed: {}", nix::errno::Errno::last()));
+    }
+
+    Ok(ret as i32)
+}
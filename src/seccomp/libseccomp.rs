@@ -0,0 +1,449 @@
+// the former libseccomp backend, kept to check the BPF compiler against it
+
+use std::{ffi::CString, fs::File, io::Read, os::unix::io::FromRawFd};
+
+use nix::libc;
+
+use super::{
+    arch::Arch, bpf::SockFilter, SyscallArg, SyscallArgCompare, SyscallFilter, SyscallRule,
+};
+
+// libseccomp accepts one comparison per argument, so a range becomes aligned masked blocks
+fn expand(arg: &SyscallArg) -> Vec<SyscallArg> {
+    match arg.compare {
+        SyscallArgCompare::Range { min, max } => range_blocks(min, max)
+            .into_iter()
+            .map(|(mask, value)| {
+                SyscallArg::new(arg.index, SyscallArgCompare::MaskedEqual { mask, value })
+            })
+            .collect(),
+        _ => vec![arg.clone()],
+    }
+}
+
+fn range_blocks(min: u64, max: u64) -> Vec<(u64, u64)> {
+    let mut blocks = vec![];
+    let mut start = min as u128;
+    let end = max as u128 + 1;
+
+    while start < end {
+        let mut bits = match start {
+            0 => 64,
+            _ => start.trailing_zeros(),
+        };
+        while start + (1 << bits) > end {
+            bits -= 1;
+        }
+
+        let mask = !((1u128 << bits) - 1) as u64;
+        blocks.push((mask, start as u64));
+        start += 1 << bits;
+    }
+
+    blocks
+}
+
+fn to_seccomp_comparison(arg: &SyscallArg) -> seccomp_sys::scmp_arg_cmp {
+    use seccomp_sys::scmp_compare::*;
+
+    let (op, datum_a, datum_b) = match arg.compare {
+        SyscallArgCompare::Equal(value) => (SCMP_CMP_EQ, value, 0),
+        SyscallArgCompare::NotEqual(value) => (SCMP_CMP_NE, value, 0),
+        SyscallArgCompare::Less(value) => (SCMP_CMP_LT, value, 0),
+        SyscallArgCompare::LessOrEqual(value) => (SCMP_CMP_LE, value, 0),
+        SyscallArgCompare::Greater(value) => (SCMP_CMP_GT, value, 0),
+        SyscallArgCompare::GreaterOrEqual(value) => (SCMP_CMP_GE, value, 0),
+        SyscallArgCompare::MaskedEqual { mask, value } => (SCMP_CMP_MASKED_EQ, mask, value),
+        SyscallArgCompare::Range { .. } => unreachable!("ranges are expanded first"),
+    };
+
+    seccomp_sys::scmp_arg_cmp {
+        arg: arg.index,
+        op,
+        datum_a,
+        datum_b,
+    }
+}
+
+// every combination of the expanded arguments becomes its own libseccomp rule
+fn to_seccomp_comparisons(rule: &SyscallRule) -> Vec<Vec<seccomp_sys::scmp_arg_cmp>> {
+    let mut rules: Vec<Vec<SyscallArg>> = vec![vec![]];
+
+    for arg in &rule.args {
+        rules = rules
+            .iter()
+            .flat_map(|rule| {
+                expand(arg).into_iter().map(move |arg| {
+                    let mut rule = rule.clone();
+                    rule.push(arg);
+                    rule
+                })
+            })
+            .collect();
+    }
+
+    rules
+        .iter()
+        .map(|rule| rule.iter().map(to_seccomp_comparison).collect())
+        .collect()
+}
+
+pub fn export(filter: &SyscallFilter) -> Result<Vec<SockFilter>, String> {
+    let arches = filter.arches()?;
+    let context = unsafe { seccomp_sys::seccomp_init(filter.default_action.to_seccomp_action()) };
+    if context.is_null() {
+        return Err("seccomp_init failed".to_string());
+    }
+
+    let result = build(context, filter, &arches).and_then(|_| export_bpf(context));
+    unsafe { seccomp_sys::seccomp_release(context) };
+    result
+}
+
+fn build(
+    context: *mut seccomp_sys::scmp_filter_ctx,
+    filter: &SyscallFilter,
+    arches: &[&Arch],
+) -> Result<(), String> {
+    let ret = unsafe {
+        seccomp_sys::seccomp_attr_set(
+            context,
+            seccomp_sys::scmp_filter_attr::SCMP_FLTATR_ACT_BADARCH,
+            filter.bad_arch_action.to_seccomp_action(),
+        )
+    };
+    if ret < 0 {
+        return Err(format!("seccomp_attr_set failed: {}", ret));
+    }
+
+    let native = Arch::native();
+    for arch in arches {
+        let ret = unsafe { seccomp_sys::seccomp_arch_add(context, arch.token) };
+        if ret < 0 && ret != -libc::EEXIST {
+            return Err(format!("seccomp_arch_add failed: {}", ret));
+        }
+    }
+    if !arches.iter().any(|arch| arch.token == native.token) {
+        unsafe { seccomp_sys::seccomp_arch_remove(context, native.token) };
+    }
+
+    for rule in &filter.rules {
+        // libseccomp refuses rules that repeat the default action
+        if rule.action == filter.default_action {
+            continue;
+        }
+
+        let name = CString::new(rule.name.clone()).unwrap();
+        let syscall_id = unsafe { seccomp_sys::seccomp_syscall_resolve_name(name.as_ptr()) };
+        if syscall_id == -1 {
+            return Err(format!("unknown system call: {}", rule.name));
+        }
+
+        for comparisons in to_seccomp_comparisons(rule) {
+            let ret = unsafe {
+                seccomp_sys::seccomp_rule_add_array(
+                    context,
+                    rule.action.to_seccomp_action(),
+                    syscall_id,
+                    comparisons.len() as u32,
+                    comparisons.as_ptr(),
+                )
+            };
+            if ret < 0 {
+                return Err(format!("seccomp_rule_add failed for {:?}: {}", rule, ret));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn export_bpf(context: *mut seccomp_sys::scmp_filter_ctx) -> Result<Vec<SockFilter>, String> {
+    let mut fds = [0; 2];
+    if unsafe { libc::pipe(fds.as_mut_ptr()) } != 0 {
+        return Err("pipe failed".to_string());
+    }
+    let mut reader = unsafe { File::from_raw_fd(fds[0]) };
+
+    let ret = unsafe { seccomp_sys::seccomp_export_bpf(context, fds[1]) };
+    unsafe { libc::close(fds[1]) };
+    if ret < 0 {
+        return Err(format!("seccomp_export_bpf failed: {}", ret));
+    }
+
+    let mut bytes = vec![];
+    reader
+        .read_to_end(&mut bytes)
+        .map_err(|err| err.to_string())?;
+
+    Ok(bytes
+        .chunks_exact(8)
+        .map(|chunk| SockFilter {
+            code: u16::from_ne_bytes([chunk[0], chunk[1]]),
+            jt: chunk[2],
+            jf: chunk[3],
+            k: u32::from_ne_bytes([chunk[4], chunk[5], chunk[6], chunk[7]]),
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::seccomp::{bpf, SyscallFilterAction};
+
+    // a classic BPF interpreter for what either backend emits
+    fn run(program: &[SockFilter], data: &[u8; 64]) -> u32 {
+        let (mut a, mut x) = (0u32, 0u32);
+        let mut memory = [0u32; 16];
+        let mut pc = 0;
+
+        loop {
+            let SockFilter { code, jt, jf, k } = program[pc];
+            pc += 1;
+
+            let load = |offset: u32| {
+                let offset = offset as usize;
+                u32::from_ne_bytes([
+                    data[offset],
+                    data[offset + 1],
+                    data[offset + 2],
+                    data[offset + 3],
+                ])
+            };
+            let branch = |taken: bool| if taken { jt as usize } else { jf as usize };
+
+            match code {
+                0x00 => a = k,
+                0x01 => x = k,
+                0x20 => a = load(k),
+                0x60 => a = memory[k as usize],
+                0x61 => x = memory[k as usize],
+                0x02 => memory[k as usize] = a,
+                0x03 => memory[k as usize] = x,
+                0x04 => a = a.wrapping_add(k),
+                0x0c => a = a.wrapping_add(x),
+                0x14 => a = a.wrapping_sub(k),
+                0x1c => a = a.wrapping_sub(x),
+                0x44 => a |= k,
+                0x4c => a |= x,
+                0x54 => a &= k,
+                0x5c => a &= x,
+                0x64 => a <<= k,
+                0x74 => a >>= k,
+                0xa4 => a ^= k,
+                0xac => a ^= x,
+                0x84 => a = a.wrapping_neg(),
+                0x05 => pc += k as usize,
+                0x15 => pc += branch(a == k),
+                0x1d => pc += branch(a == x),
+                0x25 => pc += branch(a > k),
+                0x2d => pc += branch(a > x),
+                0x35 => pc += branch(a >= k),
+                0x3d => pc += branch(a >= x),
+                0x45 => pc += branch(a & k != 0),
+                0x4d => pc += branch(a & x != 0),
+                0x06 => return k,
+                0x16 => return a,
+                0x07 => x = a,
+                0x87 => a = x,
+                code => panic!("unsupported bpf instruction {:#x}", code),
+            }
+        }
+    }
+
+    fn seccomp_data(audit_arch: u32, number: i32, args: &[u64; 6]) -> [u8; 64] {
+        let mut data = [0u8; 64];
+        data[0..4].copy_from_slice(&number.to_ne_bytes());
+        data[4..8].copy_from_slice(&audit_arch.to_ne_bytes());
+        for (i, arg) in args.iter().enumerate() {
+            data[16 + i * 8..24 + i * 8].copy_from_slice(&arg.to_ne_bytes());
+        }
+        data
+    }
+
+    // values on and around every datum the rules compare against
+    fn arg_candidates(filter: &SyscallFilter, index: u32) -> Vec<u64> {
+        let mut data = vec![0, 1, 0xffffffff, 1 << 32, u64::MAX];
+        for arg in filter.rules.iter().flat_map(|rule| &rule.args) {
+            if arg.index != index {
+                continue;
+            }
+            match arg.compare {
+                SyscallArgCompare::Equal(value)
+                | SyscallArgCompare::NotEqual(value)
+                | SyscallArgCompare::Less(value)
+                | SyscallArgCompare::LessOrEqual(value)
+                | SyscallArgCompare::Greater(value)
+                | SyscallArgCompare::GreaterOrEqual(value) => data.push(value),
+                SyscallArgCompare::MaskedEqual { mask, value } => {
+                    data.extend([value, value | !mask, value ^ (mask & mask.wrapping_neg())])
+                }
+                SyscallArgCompare::Range { min, max } => data.extend([min, max]),
+            }
+        }
+
+        let mut candidates = vec![];
+        for value in data {
+            candidates.extend([
+                value,
+                value.wrapping_sub(1),
+                value.wrapping_add(1),
+                value ^ (1 << 32),
+            ]);
+        }
+        candidates.sort_unstable();
+        candidates.dedup();
+        candidates
+    }
+
+    fn assert_same_behavior(filter: &SyscallFilter) {
+        let arches = filter.arches().unwrap();
+        let ours = bpf::compile(filter, &arches).unwrap();
+        let theirs = export(filter).unwrap();
+
+        let indices: Vec<u32> = {
+            let mut indices: Vec<u32> = filter
+                .rules
+                .iter()
+                .flat_map(|rule| rule.args.iter().map(|arg| arg.index))
+                .collect();
+            indices.sort_unstable();
+            indices.dedup();
+            indices
+        };
+        let mut arg_sets = vec![[0u64; 6], [u64::MAX; 6]];
+        for index in &indices {
+            for value in arg_candidates(filter, *index) {
+                for other in &indices {
+                    for other_value in arg_candidates(filter, *other) {
+                        let mut args = [0u64; 6];
+                        args[*other as usize] = other_value;
+                        args[*index as usize] = value;
+                        arg_sets.push(args);
+                    }
+                }
+            }
+        }
+
+        for arch in super::super::arch::ARCHES.iter() {
+            let mut numbers: Vec<i32> = vec![-1, 0, 0x3fffffff, 0x40000000, 0x7fffffff, 1000];
+            for rule in &filter.rules {
+                if let Some(number) = arch.resolve(&rule.name) {
+                    numbers.extend([number - 1, number, number + 1]);
+                }
+            }
+            // libseccomp also applies socket and ipc rules to the i386 multiplexers
+            if arch.name == "x86" {
+                numbers.retain(|number| *number != 102 && *number != 117);
+            }
+
+            for audit_arch in [arch.audit_arch, 0] {
+                for number in &numbers {
+                    for args in &arg_sets {
+                        let data = seccomp_data(audit_arch, *number, args);
+                        assert_eq!(
+                            run(&ours, &data),
+                            run(&theirs, &data),
+                            "{} syscall {} args {:x?}",
+                            arch.name,
+                            number,
+                            args
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn builtin_profiles_match_libseccomp() {
+        for name in ["native", "runtime", "compile"] {
+            assert_same_behavior(&SyscallFilter::profile(name).unwrap());
+        }
+    }
+
+    #[test]
+    fn compat_architectures_match_libseccomp() {
+        for architectures in [vec!["x86_64", "x86"], vec!["x86_64", "x32"], vec!["x32"]] {
+            let mut filter = SyscallFilter::runtime();
+            filter.architectures = architectures.into_iter().map(str::to_string).collect();
+            filter.bad_arch_action = SyscallFilterAction::Trap;
+            assert_same_behavior(&filter);
+        }
+    }
+
+    #[test]
+    fn comparisons_match_libseccomp() {
+        let mut filter = SyscallFilter::new(SyscallFilterAction::Errno(libc::EPERM as u32));
+        filter
+            .add_with_args(
+                "read",
+                SyscallFilterAction::Allow,
+                vec![SyscallArg::new(0, SyscallArgCompare::Equal(0x1_0000_0003))],
+            )
+            .add_with_args(
+                "write",
+                SyscallFilterAction::Allow,
+                vec![SyscallArg::new(0, SyscallArgCompare::NotEqual(2))],
+            )
+            .add_with_args(
+                "close",
+                SyscallFilterAction::Log,
+                vec![SyscallArg::new(0, SyscallArgCompare::Less(0x2_0000_0000))],
+            )
+            .add_with_args(
+                "dup",
+                SyscallFilterAction::Allow,
+                vec![SyscallArg::new(0, SyscallArgCompare::LessOrEqual(10))],
+            )
+            .add_with_args(
+                "dup2",
+                SyscallFilterAction::Trace(7),
+                vec![SyscallArg::new(1, SyscallArgCompare::Greater(0xffff_ffff))],
+            )
+            .add_with_args(
+                "lseek",
+                SyscallFilterAction::Allow,
+                vec![
+                    SyscallArg::new(0, SyscallArgCompare::GreaterOrEqual(3)),
+                    SyscallArg::new(2, SyscallArgCompare::Equal(libc::SEEK_SET as u64)),
+                ],
+            )
+            .add_with_args(
+                "mmap",
+                SyscallFilterAction::Allow,
+                vec![SyscallArg::new(
+                    2,
+                    SyscallArgCompare::MaskedEqual {
+                        mask: (libc::PROT_WRITE | libc::PROT_EXEC) as u64,
+                        value: libc::PROT_WRITE as u64,
+                    },
+                )],
+            )
+            .add_with_args(
+                "kill",
+                SyscallFilterAction::Trap,
+                vec![SyscallArg::new(
+                    1,
+                    SyscallArgCompare::Range {
+                        min: 3,
+                        max: 0x1_0000_0010,
+                    },
+                )],
+            )
+            .add("getpid", SyscallFilterAction::KillThread);
+
+        assert_same_behavior(&filter);
+
+        // libseccomp truncates the masked blocks of a range past 32 bits on i386, keep it below
+        for rule in filter.rules.iter_mut().filter(|rule| rule.name == "kill") {
+            rule.args[0].compare = SyscallArgCompare::Range {
+                min: 3,
+                max: 0xffff_0010,
+            };
+        }
+        filter.architectures = vec!["x86_64".to_string(), "x86".to_string()];
+        assert_same_behavior(&filter);
+    }
+}
@@ -0,0 +1,707 @@
+use std::{
+    ffi::{CString, OsString},
+    fs,
+    os::unix::{ffi::OsStringExt, io::RawFd},
+    path::{Component, Path, PathBuf},
+    str::FromStr,
+    thread,
+};
+
+use nix::{
+    cmsg_space, libc,
+    sys::{
+        socket::{
+            recvmsg, sendmsg, socketpair, AddressFamily, ControlMessage, ControlMessageOwned,
+            MsgFlags, SockFlag, SockType,
+        },
+        uio::IoVec,
+    },
+};
+
+use crate::seccomp::{
+    syscall_name, SyscallArg, SyscallArgCompare, SyscallFilter, SyscallFilterAction,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MediationAction {
+    Allow,
+    Deny,
+    // allow and print the request
+    Log,
+}
+
+impl FromStr for MediationAction {
+    type Err = String;
+
+    fn from_str(action: &str) -> Result<Self, Self::Err> {
+        match action {
+            "allow" => Ok(MediationAction::Allow),
+            "deny" => Ok(MediationAction::Deny),
+            "log" => Ok(MediationAction::Log),
+            _ => Err(format!("unknown mediation action: {}", action)),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum MediationTarget {
+    // files opened at or below the path, as the sandboxed program sees it
+    Path(PathBuf),
+    // loopback connections to the port
+    Port(u16),
+}
+
+#[derive(Debug, Clone)]
+pub struct MediationRule {
+    pub action: MediationAction,
+    pub target: MediationTarget,
+}
+
+// <action>:path:<prefix> or <action>:port:<port>
+impl FromStr for MediationRule {
+    type Err = String;
+
+    fn from_str(rule: &str) -> Result<Self, Self::Err> {
+        let mut parts = rule.splitn(3, ':');
+        let (action, kind, value) = match (parts.next(), parts.next(), parts.next()) {
+            (Some(action), Some(kind), Some(value)) => (action, kind, value),
+            _ => return Err(format!("invalid mediation rule: {}", rule)),
+        };
+
+        let target = match kind {
+            "path" if Path::new(value).is_absolute() => {
+                MediationTarget::Path(normalize(Path::new(value)))
+            }
+            "path" => return Err(format!("mediated path is not absolute: {}", value)),
+            "port" => MediationTarget::Port(
+                value
+                    .parse()
+                    .map_err(|_| format!("invalid mediated port: {}", value))?,
+            ),
+            _ => return Err(format!("unknown mediation target: {}", kind)),
+        };
+
+        Ok(MediationRule {
+            action: action.parse()?,
+            target,
+        })
+    }
+}
+
+const MEDIATED_CALLS: [&str; 2] = ["openat", "connect"];
+
+#[derive(Debug, Clone)]
+pub struct SupervisorPolicy {
+    pub rules: Vec<MediationRule>,
+    pub default_action: MediationAction,
+}
+
+impl SupervisorPolicy {
+    pub fn new(default_action: MediationAction) -> Self {
+        SupervisorPolicy {
+            rules: vec![],
+            default_action,
+        }
+    }
+
+    // stacked under the profile, the kernel lets its notifications win over the profile's allows
+    pub fn notify_filter(&self, profile: &SyscallFilter) -> SyscallFilter {
+        let mut filter = SyscallFilter::new(SyscallFilterAction::Allow);
+        filter.architectures = profile.architectures.clone();
+        filter.bad_arch_action = SyscallFilterAction::Allow;
+
+        for rule in &profile.rules {
+            let allowed = matches!(
+                rule.action,
+                SyscallFilterAction::Allow | SyscallFilterAction::Log
+            );
+            if allowed && MEDIATED_CALLS.contains(&rule.name.as_str()) {
+                filter.add_with_args(&rule.name, SyscallFilterAction::Allow, rule.args.clone());
+            }
+        }
+        for syscall in MEDIATED_CALLS {
+            filter.add(syscall, SyscallFilterAction::Notify);
+        }
+
+        filter
+    }
+
+    // the supervisor only sees the calls that the profile's own rules do not allow
+    pub fn mediate(&self, filter: &mut SyscallFilter) {
+        for syscall in MEDIATED_CALLS {
+            filter.add(syscall, SyscallFilterAction::Allow);
+        }
+
+        // connections need an internet socket first
+        let connects = self.default_action != MediationAction::Deny
+            || self
+                .rules
+                .iter()
+                .any(|rule| matches!(rule.target, MediationTarget::Port(_)));
+        if connects {
+            for domain in [libc::AF_INET, libc::AF_INET6] {
+                filter.add_with_args(
+                    "socket",
+                    SyscallFilterAction::Allow,
+                    vec![SyscallArg::new(0, SyscallArgCompare::Equal(domain as u64))],
+                );
+            }
+        }
+    }
+
+    // the first matching rule decides
+    fn path_action(&self, path: &Path) -> MediationAction {
+        self.rules
+            .iter()
+            .find(|rule| matches!(&rule.target, MediationTarget::Path(prefix) if path.starts_with(prefix)))
+            .map_or(self.default_action, |rule| rule.action)
+    }
+
+    fn port_action(&self, port: u16) -> MediationAction {
+        self.rules
+            .iter()
+            .find(|rule| matches!(rule.target, MediationTarget::Port(p) if p == port))
+            .map_or(self.default_action, |rule| rule.action)
+    }
+}
+
+// a connected pair, the child sends its listener through one end and the parent receives it on the other
+pub fn channel() -> (RawFd, RawFd) {
+    socketpair(
+        AddressFamily::Unix,
+        SockType::SeqPacket,
+        None,
+        SockFlag::SOCK_CLOEXEC,
+    )
+    .expect("socketpair failed")
+}
+
+pub fn send_listener(channel: RawFd, listener: RawFd) {
+    let fds = [listener];
+    sendmsg(
+        channel,
+        &[IoVec::from_slice(&[0u8])],
+        &[ControlMessage::ScmRights(&fds)],
+        MsgFlags::empty(),
+        None,
+    )
+    .expect("sending the seccomp listener failed");
+    unsafe { libc::close(listener) };
+}
+
+pub struct Supervisor {
+    policy: SupervisorPolicy,
+    channel: RawFd,
+    listener: Option<RawFd>,
+    finished: bool,
+}
+
+impl Drop for Supervisor {
+    fn drop(&mut self) {
+        unsafe {
+            libc::close(self.channel);
+            if let Some(listener) = self.listener {
+                libc::close(listener);
+            }
+        }
+    }
+}
+
+impl Supervisor {
+    pub fn new(policy: SupervisorPolicy, channel: RawFd) -> Self {
+        Supervisor {
+            policy,
+            channel,
+            listener: None,
+            finished: false,
+        }
+    }
+
+    // waits up to `timeout` milliseconds for a notification and answers it
+    pub fn serve(&mut self, timeout: i32) {
+        if self.listener.is_none() && !self.finished {
+            self.receive_listener();
+        }
+        let listener = match self.listener {
+            Some(listener) => listener,
+            None => {
+                unsafe { libc::usleep(timeout as u32 * 1000) };
+                return;
+            }
+        };
+
+        let mut poll = libc::pollfd {
+            fd: listener,
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        if unsafe { libc::poll(&mut poll, 1, timeout) } <= 0 {
+            return;
+        }
+        if poll.revents & libc::POLLIN != 0 {
+            self.handle(listener);
+        } else if poll.revents & (libc::POLLHUP | libc::POLLERR) != 0 {
+            // every process using the filter is gone
+            unsafe { libc::close(listener) };
+            self.listener = None;
+            self.finished = true;
+        }
+    }
+
+    fn receive_listener(&mut self) {
+        let mut buf = [0u8; 1];
+        let mut space = cmsg_space!([RawFd; 1]);
+        let message = match recvmsg(
+            self.channel,
+            &[IoVec::from_mut_slice(&mut buf)],
+            Some(&mut space),
+            MsgFlags::MSG_DONTWAIT,
+        ) {
+            Ok(message) => message,
+            Err(_) => return,
+        };
+
+        for cmsg in message.cmsgs() {
+            if let ControlMessageOwned::ScmRights(fds) = cmsg {
+                self.listener = fds.first().copied();
+            }
+        }
+        // the child exited without installing a filter
+        if message.bytes == 0 {
+            self.finished = true;
+        }
+    }
+
+    fn handle(&self, listener: RawFd) {
+        let mut request: libc::seccomp_notif = unsafe { std::mem::zeroed() };
+        if unsafe { libc::ioctl(listener, libc::SECCOMP_IOCTL_NOTIF_RECV, &mut request) } != 0 {
+            // the caller was killed before we got to it
+            return;
+        }
+
+        let result = match syscall_name(request.data.arch, request.data.nr).as_deref() {
+            Some("openat") => self.open(listener, &request),
+            Some("connect") => self.connect(listener, &request),
+            _ => Err(libc::ENOSYS),
+        };
+
+        match result {
+            Ok(Reply::Value(value)) => respond(listener, request.id, value, 0),
+            Ok(Reply::Fd(fd)) => {
+                let addfd = libc::seccomp_notif_addfd {
+                    id: request.id,
+                    flags: libc::SECCOMP_ADDFD_FLAG_SEND as u32,
+                    srcfd: fd as u32,
+                    newfd: 0,
+                    newfd_flags: match request.data.args[2] as i32 & libc::O_CLOEXEC {
+                        0 => 0,
+                        _ => libc::O_CLOEXEC as u32,
+                    },
+                };
+                // the kernel answers the call with the installed descriptor
+                let ret = unsafe { libc::ioctl(listener, libc::SECCOMP_IOCTL_NOTIF_ADDFD, &addfd) };
+                if ret < 0 {
+                    respond(listener, request.id, 0, -errno());
+                }
+                unsafe { libc::close(fd) };
+            }
+            Err(err) => respond(listener, request.id, 0, -err),
+        }
+    }
+
+    // opens the file in the child's root ourselves so the child cannot swap the path after the check,
+    // with no more access to it than the child has
+    fn open(&self, listener: RawFd, request: &libc::seccomp_notif) -> Result<Reply, i32> {
+        let pid = request.pid;
+        let args = request.data.args;
+        let path = read_path(pid, args[1]).ok_or(libc::EFAULT)?;
+        if !valid(listener, request.id) {
+            return Err(libc::ENOENT);
+        }
+
+        // the directory is resolved first so the policy sees where the file really is
+        let child_path = child_path(pid, args[0] as i32, &path).ok_or(libc::EACCES)?;
+        let (dir, name) = match (child_path.parent(), child_path.file_name()) {
+            (Some(dir), Some(name)) => (dir, Path::new(name)),
+            _ => (Path::new("/"), Path::new(".")),
+        };
+        let credentials = FileCredentials::of(pid).ok_or(libc::ESRCH)?;
+        let root = open_path(&format!("/proc/{}/root", pid)).ok_or(libc::EACCES)?;
+        let dir = credentials.open(|| {
+            openat2(
+                root,
+                dir.strip_prefix("/").unwrap(),
+                libc::O_PATH | libc::O_DIRECTORY,
+                0,
+                libc::RESOLVE_IN_ROOT | libc::RESOLVE_NO_MAGICLINKS,
+            )
+        });
+        unsafe { libc::close(root) };
+        let dir = dir?;
+
+        let real_path = fs::read_link(format!("/proc/self/fd/{}", dir))
+            .ok()
+            .and_then(|dir| host_to_child(pid, &dir))
+            .map(|dir| normalize(&dir.join(name)));
+        let action = match &real_path {
+            Some(real_path) => {
+                let action = self.policy.path_action(real_path);
+                self.log("openat", &real_path.display().to_string(), action);
+                action
+            }
+            None => MediationAction::Deny,
+        };
+        if action == MediationAction::Deny {
+            unsafe { libc::close(dir) };
+            return Err(libc::EACCES);
+        }
+
+        // a symlink as the last component would lead past the check
+        let fd = credentials.open(|| {
+            openat2(
+                dir,
+                name,
+                args[2] as i32,
+                args[3],
+                libc::RESOLVE_BENEATH | libc::RESOLVE_NO_SYMLINKS,
+            )
+        });
+        unsafe { libc::close(dir) };
+        fd.map(Reply::Fd)
+    }
+
+    // connects a duplicate of the child's socket to our own copy of the address
+    fn connect(&self, listener: RawFd, request: &libc::seccomp_notif) -> Result<Reply, i32> {
+        let pid = request.pid;
+        let args = request.data.args;
+        let len = (args[2] as usize).min(std::mem::size_of::<libc::sockaddr_storage>());
+        let address = read_memory(pid, args[1], len).ok_or(libc::EFAULT)?;
+        if address.len() < len {
+            return Err(libc::EFAULT);
+        }
+        if !valid(listener, request.id) {
+            return Err(libc::ENOENT);
+        }
+
+        let action = match loopback_port(&address) {
+            Some(port) => {
+                let action = self.policy.port_action(port);
+                self.log("connect", &format!("loopback port {}", port), action);
+                action
+            }
+            None => {
+                self.log(
+                    "connect",
+                    "non-loopback address",
+                    self.policy.default_action,
+                );
+                self.policy.default_action
+            }
+        };
+        if action == MediationAction::Deny {
+            return Err(libc::EACCES);
+        }
+
+        let socket = child_fd(pid, args[0] as i32)?;
+        let ret = unsafe {
+            libc::connect(
+                socket,
+                address.as_ptr() as *const libc::sockaddr,
+                address.len() as libc::socklen_t,
+            )
+        };
+        let result = match ret {
+            0 => Ok(Reply::Value(0)),
+            _ => Err(errno()),
+        };
+        unsafe { libc::close(socket) };
+        result
+    }
+
+    fn log(&self, syscall: &str, target: &str, action: MediationAction) {
+        if action == MediationAction::Log {
+            println!("mediated {} of {}", syscall, target);
+        }
+    }
+}
+
+enum Reply {
+    Value(i64),
+    // a descriptor to install in the child as the call's result
+    Fd(RawFd),
+}
+
+fn errno() -> i32 {
+    nix::errno::Errno::last() as i32
+}
+
+fn respond(listener: RawFd, id: u64, val: i64, error: i32) {
+    let response = libc::seccomp_notif_resp {
+        id,
+        val,
+        error,
+        flags: 0,
+    };
+    unsafe { libc::ioctl(listener, libc::SECCOMP_IOCTL_NOTIF_SEND, &response) };
+}
+
+// the request is still pending, so the pid was not reused while we read its memory
+fn valid(listener: RawFd, id: u64) -> bool {
+    unsafe { libc::ioctl(listener, libc::SECCOMP_IOCTL_NOTIF_ID_VALID, &id) == 0 }
+}
+
+fn read_memory(pid: u32, address: u64, len: usize) -> Option<Vec<u8>> {
+    let mut buf = vec![0u8; len];
+    let local = libc::iovec {
+        iov_base: buf.as_mut_ptr() as *mut libc::c_void,
+        iov_len: len,
+    };
+    let remote = libc::iovec {
+        iov_base: address as *mut libc::c_void,
+        iov_len: len,
+    };
+
+    let read = unsafe { libc::process_vm_readv(pid as libc::pid_t, &local, 1, &remote, 1, 0) };
+    if read < 0 {
+        return None;
+    }
+    buf.truncate(read as usize);
+    Some(buf)
+}
+
+// reads page by page, the string may end right before an unmapped page
+fn read_path(pid: u32, mut address: u64) -> Option<PathBuf> {
+    let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as u64;
+    let mut path = vec![];
+
+    while path.len() < libc::PATH_MAX as usize {
+        let len = (page_size - address % page_size) as usize;
+        let chunk = read_memory(pid, address, len)?;
+        if let Some(end) = chunk.iter().position(|byte| *byte == 0) {
+            path.extend_from_slice(&chunk[..end]);
+            return Some(PathBuf::from(OsString::from_vec(path)));
+        }
+        if chunk.len() < len {
+            return None;
+        }
+        path.extend_from_slice(&chunk);
+        address += len as u64;
+    }
+
+    None
+}
+
+// lexically, `..` never leaves the root
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::from("/");
+    for component in path.components() {
+        match component {
+            Component::Normal(name) => normalized.push(name),
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            _ => {}
+        }
+    }
+    normalized
+}
+
+// a path of the host as the child sees it from inside its root
+fn host_to_child(pid: u32, path: &Path) -> Option<PathBuf> {
+    let root = fs::read_link(format!("/proc/{}/root", pid)).ok()?;
+    Some(Path::new("/").join(path.strip_prefix(root).ok()?))
+}
+
+fn child_path(pid: u32, dirfd: i32, path: &Path) -> Option<PathBuf> {
+    if path.is_absolute() {
+        return Some(normalize(path));
+    }
+
+    let base = match dirfd {
+        libc::AT_FDCWD => fs::read_link(format!("/proc/{}/cwd", pid)).ok()?,
+        fd => fs::read_link(format!("/proc/{}/fd/{}", pid, fd)).ok()?,
+    };
+    Some(normalize(&host_to_child(pid, &base)?.join(path)))
+}
+
+fn open_path(path: &str) -> Option<RawFd> {
+    let path = CString::new(path).unwrap();
+    let fd = unsafe {
+        libc::open(
+            path.as_ptr(),
+            libc::O_PATH | libc::O_DIRECTORY | libc::O_CLOEXEC,
+        )
+    };
+    match fd {
+        fd if fd < 0 => None,
+        fd => Some(fd),
+    }
+}
+
+fn openat2(dir: RawFd, path: &Path, flags: i32, mode: u64, resolve: u64) -> Result<RawFd, i32> {
+    let path = match path.as_os_str().is_empty() {
+        true => CString::new(".").unwrap(),
+        false => {
+            CString::new(path.as_os_str().to_os_string().into_vec()).map_err(|_| libc::EINVAL)?
+        }
+    };
+
+    let mut how: libc::open_how = unsafe { std::mem::zeroed() };
+    how.flags = (flags | libc::O_CLOEXEC) as u64;
+    if flags & (libc::O_CREAT | libc::O_TMPFILE) != 0 {
+        how.mode = mode & 0o7777;
+    }
+    how.resolve = resolve;
+
+    let fd = unsafe {
+        libc::syscall(
+            libc::SYS_openat2,
+            dir,
+            path.as_ptr(),
+            &how as *const libc::open_how,
+            std::mem::size_of::<libc::open_how>(),
+        )
+    };
+    match fd {
+        fd if fd < 0 => Err(errno()),
+        fd => Ok(fd as RawFd),
+    }
+}
+
+// what the kernel checks a thread's file access against
+struct FileCredentials {
+    uid: libc::uid_t,
+    gid: libc::gid_t,
+    groups: Vec<libc::gid_t>,
+}
+
+#[repr(C)]
+struct CapHeader {
+    version: u32,
+    pid: i32,
+}
+
+#[repr(C)]
+struct CapData {
+    effective: u32,
+    permitted: u32,
+    inheritable: u32,
+}
+
+const CAPABILITY_VERSION_3: u32 = 0x20080522;
+
+impl FileCredentials {
+    fn of(pid: u32) -> Option<Self> {
+        let status = fs::read_to_string(format!("/proc/{}/status", pid)).ok()?;
+        let field = |name: &str| -> Option<Vec<u32>> {
+            let value = status.lines().find_map(|line| line.strip_prefix(name))?;
+            value.split_whitespace().map(|id| id.parse().ok()).collect()
+        };
+
+        // real, effective, saved and file system ids, in that order
+        Some(FileCredentials {
+            uid: *field("Uid:")?.get(3)?,
+            gid: *field("Gid:")?.get(3)?,
+            groups: field("Groups:")?,
+        })
+    }
+
+    // the kernel keeps credentials per thread, so a thread of our own takes on the child's and
+    // never gets our capabilities back
+    fn open(&self, open: impl FnOnce() -> Result<RawFd, i32> + Send) -> Result<RawFd, i32> {
+        // without root we hold no more than the child, nor could we switch
+        if unsafe { libc::geteuid() } != 0 {
+            return open();
+        }
+
+        thread::scope(|scope| {
+            scope
+                .spawn(|| {
+                    self.assume()?;
+                    open()
+                })
+                .join()
+                .unwrap_or(Err(libc::EACCES))
+        })
+    }
+
+    // the libc wrappers of setgroups would change every thread of ours
+    fn assume(&self) -> Result<(), i32> {
+        let ret =
+            unsafe { libc::syscall(libc::SYS_setgroups, self.groups.len(), self.groups.as_ptr()) };
+        if ret != 0 {
+            return Err(errno());
+        }
+
+        // both return the previous id, a second call tells whether the first one took
+        unsafe {
+            libc::syscall(libc::SYS_setfsgid, self.gid);
+            libc::syscall(libc::SYS_setfsuid, self.uid);
+        }
+        let (gid, uid) = unsafe {
+            (
+                libc::syscall(libc::SYS_setfsgid, -1i32) as libc::gid_t,
+                libc::syscall(libc::SYS_setfsuid, -1i32) as libc::uid_t,
+            )
+        };
+        if gid != self.gid || uid != self.uid {
+            return Err(libc::EPERM);
+        }
+
+        // a child running as root has none of root's capabilities either
+        let mut header = CapHeader {
+            version: CAPABILITY_VERSION_3,
+            pid: 0,
+        };
+        let data: [CapData; 2] = unsafe { std::mem::zeroed() };
+        if unsafe { libc::syscall(libc::SYS_capset, &mut header, data.as_ptr()) } != 0 {
+            return Err(errno());
+        }
+        Ok(())
+    }
+}
+
+// a notification names a thread, descriptors belong to its thread group leader
+fn child_fd(pid: u32, fd: i32) -> Result<RawFd, i32> {
+    let status = fs::read_to_string(format!("/proc/{}/status", pid)).map_err(|_| libc::ESRCH)?;
+    let tgid: libc::pid_t = status
+        .lines()
+        .find_map(|line| line.strip_prefix("Tgid:"))
+        .and_then(|tgid| tgid.trim().parse().ok())
+        .ok_or(libc::ESRCH)?;
+
+    let pidfd = unsafe { libc::syscall(libc::SYS_pidfd_open, tgid, 0) };
+    if pidfd < 0 {
+        return Err(libc::ESRCH);
+    }
+    let socket = unsafe { libc::syscall(libc::SYS_pidfd_getfd, pidfd, fd, 0) };
+    let result = match socket {
+        socket if socket < 0 => Err(errno()),
+        socket => Ok(socket as RawFd),
+    };
+    unsafe { libc::close(pidfd as i32) };
+    result
+}
+
+fn loopback_port(address: &[u8]) -> Option<u16> {
+    if address.len() < 4 {
+        return None;
+    }
+    let family = u16::from_ne_bytes([address[0], address[1]]) as i32;
+    let port = u16::from_be_bytes([address[2], address[3]]);
+
+    let loopback = match family {
+        libc::AF_INET if address.len() >= 8 => address[4] == 127,
+        libc::AF_INET6 if address.len() >= 24 => {
+            let ip = &address[8..24];
+            let v4_mapped = ip[..10].iter().all(|byte| *byte == 0) && ip[10..12] == [0xff, 0xff];
+            ip[..15].iter().all(|byte| *byte == 0) && ip[15] == 1 || v4_mapped && ip[12] == 127
+        }
+        _ => false,
+    };
+
+    match loopback {
+        true => Some(port),
+        false => None,
+    }
+}
@@ -0,0 +1,257 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    convert::TryFrom,
+};
+
+use nix::{
+    libc,
+    sys::{
+        ptrace::{self, Options},
+        signal::{self, Signal},
+    },
+    unistd::Pid,
+};
+
+use serde::Serialize;
+
+use crate::seccomp::{argument_shape, syscall_name};
+
+const SYS_SECCOMP: i32 = 1;
+const PTRACE_EVENT_FORK: i32 = 1;
+const PTRACE_EVENT_VFORK: i32 = 2;
+const PTRACE_EVENT_CLONE: i32 = 3;
+const PTRACE_EVENT_SECCOMP: i32 = 7;
+#[cfg(target_arch = "x86_64")]
+const AUDIT_ARCH_I386: u32 = 0x40000003;
+#[cfg(target_arch = "x86_64")]
+const AUDIT_ARCH_X86_64: u32 = 0xc000003e;
+#[cfg(target_arch = "x86_64")]
+const AUDIT_ARCH_X32: u32 = 0x4000003e;
+#[cfg(target_arch = "x86_64")]
+const X32_SYSCALL_BIT: i32 = 0x40000000;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TraceMode {
+    // kill on the first trapped call and keep it as the violation
+    Violations,
+    // record every call that the filter's trace action reports
+    Learn,
+}
+
+#[allow(dead_code)]
+#[derive(Debug, Clone, Serialize)]
+pub struct SyscallViolation {
+    pub syscall: String,
+    pub number: i32,
+    pub arch: u32,
+    pub args: [u64; 6],
+}
+
+// the SIGSYS member of the siginfo_t union
+#[repr(C)]
+struct SigsysInfo {
+    signo: i32,
+    errno: i32,
+    code: i32,
+    call_addr: *mut libc::c_void,
+    syscall: i32,
+    arch: u32,
+}
+
+pub struct Tracer {
+    child: Pid,
+    mode: TraceMode,
+    attached: bool,
+    // the threads and processes being traced, the only ones we wait for
+    tracees: BTreeSet<Pid>,
+    names: HashMap<(u32, i32), Option<String>>,
+    pub violation: Option<SyscallViolation>,
+    pub architectures: BTreeSet<u32>,
+    // system call names with the observed value of their shaped argument, if any
+    pub observed: BTreeSet<(String, Option<(u32, u64)>)>,
+    // how often each system call was made, when counting
+    pub counts: Option<BTreeMap<String, u64>>,
+}
+
+#[cfg(target_arch = "x86_64")]
+fn syscall_args(pid: Pid, arch: u32) -> [u64; 6] {
+    match ptrace::getregs(pid) {
+        Ok(regs) if arch == AUDIT_ARCH_I386 => {
+            [regs.rbx, regs.rcx, regs.rdx, regs.rsi, regs.rdi, regs.rbp]
+                .map(|reg| reg as u32 as u64)
+        }
+        Ok(regs) => [regs.rdi, regs.rsi, regs.rdx, regs.r10, regs.r8, regs.r9],
+        Err(_) => [0; 6],
+    }
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+fn syscall_args(_pid: Pid, _arch: u32) -> [u64; 6] {
+    [0; 6]
+}
+
+// x32 calls share the x86_64 audit architecture and only differ by a bit in the number
+#[cfg(target_arch = "x86_64")]
+fn abi_arch(arch: u32, number: i32) -> u32 {
+    if arch == AUDIT_ARCH_X86_64 && number & X32_SYSCALL_BIT != 0 {
+        AUDIT_ARCH_X32
+    } else {
+        arch
+    }
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+fn abi_arch(arch: u32, _number: i32) -> u32 {
+    arch
+}
+
+// architecture and number of the call a tracee is stopped at
+#[cfg(target_arch = "x86_64")]
+fn syscall_entry(pid: Pid) -> Option<(u32, i32)> {
+    let regs = ptrace::getregs(pid).ok()?;
+    let number = regs.orig_rax as i32;
+
+    // 0x23 is the code segment of 32-bit processes
+    let arch = match regs.cs {
+        0x23 => AUDIT_ARCH_I386,
+        _ => abi_arch(AUDIT_ARCH_X86_64, number),
+    };
+
+    Some((arch, number))
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+fn syscall_entry(_pid: Pid) -> Option<(u32, i32)> {
+    None
+}
+
+impl Tracer {
+    pub fn new(child: Pid, mode: TraceMode) -> Self {
+        Tracer {
+            child,
+            mode,
+            attached: false,
+            tracees: BTreeSet::from([child]),
+            names: HashMap::new(),
+            violation: None,
+            architectures: BTreeSet::new(),
+            observed: BTreeSet::new(),
+            counts: None,
+        }
+    }
+
+    // the filter's trace action reports the calls to count
+    pub fn count_syscalls(mut self) -> Self {
+        self.counts = Some(BTreeMap::new());
+        self
+    }
+
+    pub fn tracees(&self) -> Vec<Pid> {
+        self.tracees.iter().copied().collect()
+    }
+
+    // a tracee that exited, or that the kernel no longer reports to us
+    pub fn forget(&mut self, pid: Pid) {
+        self.tracees.remove(&pid);
+    }
+
+    fn syscall(&mut self, pid: Pid) -> Option<(u32, String)> {
+        let (arch, number) = syscall_entry(pid)?;
+        let name = self
+            .names
+            .entry((arch, number))
+            .or_insert_with(|| syscall_name(arch, number))
+            .clone()?;
+
+        Some((arch, name))
+    }
+
+    fn record(&mut self, pid: Pid) {
+        let (arch, name) = match self.syscall(pid) {
+            Some(syscall) => syscall,
+            None => return,
+        };
+
+        let arg =
+            argument_shape(&name).map(|index| (index, syscall_args(pid, arch)[index as usize]));
+        self.architectures.insert(arch);
+        self.observed.insert((name, arg));
+    }
+
+    fn count(&mut self, pid: Pid) {
+        if let Some((_, name)) = self.syscall(pid) {
+            if let Some(counts) = &mut self.counts {
+                *counts.entry(name).or_insert(0) += 1;
+            }
+        }
+    }
+
+    pub fn handle_stop(&mut self, pid: Pid, status: i32) {
+        // the first stop is the SIGSTOP the child raises before loading its filter
+        if !self.attached {
+            self.attached = true;
+            let mut options = Options::PTRACE_O_EXITKILL
+                | Options::PTRACE_O_TRACECLONE
+                | Options::PTRACE_O_TRACEFORK
+                | Options::PTRACE_O_TRACEVFORK
+                | Options::PTRACE_O_TRACEEXEC;
+            if self.mode == TraceMode::Learn || self.counts.is_some() {
+                options |= Options::PTRACE_O_TRACESECCOMP;
+            }
+            ptrace::setoptions(pid, options).ok();
+            ptrace::cont(pid, None).ok();
+            return;
+        }
+
+        let event = status >> 16;
+        if event != 0 {
+            match event {
+                PTRACE_EVENT_SECCOMP => match self.mode {
+                    TraceMode::Learn => self.record(pid),
+                    TraceMode::Violations => self.count(pid),
+                },
+                PTRACE_EVENT_FORK | PTRACE_EVENT_VFORK | PTRACE_EVENT_CLONE => {
+                    if let Ok(new) = ptrace::getevent(pid) {
+                        self.tracees.insert(Pid::from_raw(new as i32));
+                    }
+                }
+                _ => {}
+            }
+            ptrace::cont(pid, None).ok();
+            return;
+        }
+
+        // group-stops have no siginfo
+        let info = match ptrace::getsiginfo(pid) {
+            Ok(info) => info,
+            Err(_) => {
+                ptrace::cont(pid, None).ok();
+                return;
+            }
+        };
+
+        let signal = libc::WSTOPSIG(status);
+        if signal == libc::SIGSYS && info.si_code == SYS_SECCOMP {
+            let sigsys = unsafe { &*(&info as *const libc::siginfo_t as *const SigsysInfo) };
+            let arch = abi_arch(sigsys.arch, sigsys.syscall);
+            if self.violation.is_none() {
+                self.violation = Some(SyscallViolation {
+                    syscall: syscall_name(arch, sigsys.syscall)
+                        .unwrap_or_else(|| sigsys.syscall.to_string()),
+                    number: sigsys.syscall,
+                    arch,
+                    args: syscall_args(pid, arch),
+                });
+            }
+            signal::kill(self.child, Signal::SIGKILL).ok();
+            return;
+        }
+
+        // new threads and processes start with a SIGSTOP that must not reach them
+        let signal = match signal {
+            libc::SIGSTOP => None,
+            signal => Signal::try_from(signal).ok(),
+        };
+        ptrace::cont(pid, signal).ok();
+    }
+}
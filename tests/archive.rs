@@ -0,0 +1,226 @@
+mod common;
+
+use std::{
+    fs,
+    io::{Cursor, Write},
+    path::{Path, PathBuf},
+    process::{Command, Output},
+};
+
+use common::work_dir;
+use sandbox::archive::{extract, ExtractLimit};
+
+const LIMIT: ExtractLimit = ExtractLimit {
+    max_entries: 16,
+    max_size: 1024,
+};
+
+enum Entry<'a> {
+    File(&'a str, &'a [u8]),
+    Symlink(&'a str, &'a str),
+    Hardlink(&'a str, &'a str),
+}
+
+// names go into the header as they are, which the tar crate's own path setters would refuse
+fn tar(entries: &[Entry]) -> Vec<u8> {
+    let mut builder = tar::Builder::new(vec![]);
+    for entry in entries {
+        let mut header = tar::Header::new_old();
+        let (name, data, link): (&str, &[u8], Option<&str>) = match entry {
+            Entry::File(name, data) => {
+                header.set_entry_type(tar::EntryType::Regular);
+                (name, data, None)
+            }
+            Entry::Symlink(name, target) => {
+                header.set_entry_type(tar::EntryType::Symlink);
+                (name, &[], Some(target))
+            }
+            Entry::Hardlink(name, target) => {
+                header.set_entry_type(tar::EntryType::Link);
+                (name, &[], Some(target))
+            }
+        };
+        header.as_old_mut().name[..name.len()].copy_from_slice(name.as_bytes());
+        if let Some(link) = link {
+            header.as_old_mut().linkname[..link.len()].copy_from_slice(link.as_bytes());
+        }
+        header.set_mode(0o644);
+        header.set_size(data.len() as u64);
+        header.set_cksum();
+        builder.append(&header, data).unwrap();
+    }
+    builder.into_inner().unwrap()
+}
+
+fn zip(name: &str, data: &[u8]) -> Vec<u8> {
+    let mut writer = zip::ZipWriter::new(Cursor::new(vec![]));
+    let options =
+        zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+    writer.start_file(name, options).unwrap();
+    writer.write_all(data).unwrap();
+    writer.finish().unwrap().into_inner()
+}
+
+// returns the error, checking that nothing was left behind in or next to the target
+fn extract_err(dir: &Path, archive_name: &str, archive: &[u8]) -> String {
+    let archive_path = dir.join(archive_name);
+    fs::write(&archive_path, archive).unwrap();
+    let target = dir.join("target");
+
+    let err = extract(&archive_path, &target, &LIMIT).unwrap_err();
+    assert!(!target.exists(), "{} was left behind", target.display());
+    err
+}
+
+#[test]
+fn parent_and_absolute_entries_are_rejected() {
+    let dir = work_dir("paths");
+    for name in ["../escape", "a/../../escape", "/tmp/escape"] {
+        let err = extract_err(&dir, "paths.tar", &tar(&[Entry::File(name, b"x")]));
+        assert!(err.contains("unsafe path"), "{}: {}", name, err);
+    }
+    assert!(!dir.join("escape").exists());
+
+    let err = extract_err(&dir, "paths.zip", &zip("../escape", b"x"));
+    assert!(err.contains("unsafe path"), "{}", err);
+    assert!(!dir.join("escape").exists());
+}
+
+#[test]
+fn links_are_rejected() {
+    let dir = work_dir("links");
+    let outside = dir.join("outside");
+    fs::create_dir_all(&outside).unwrap();
+
+    // a file through a symlink planted by an earlier entry would land outside
+    let archive = tar(&[
+        Entry::Symlink("link", outside.to_str().unwrap()),
+        Entry::File("link/escape", b"x"),
+    ]);
+    let err = extract_err(&dir, "symlink.tar", &archive);
+    assert!(err.contains("Symlink"), "{}", err);
+
+    let archive = tar(&[
+        Entry::File("file", b"x"),
+        Entry::Hardlink("hardlink", "/etc/passwd"),
+    ]);
+    let err = extract_err(&dir, "hardlink.tar", &archive);
+    assert!(err.contains("Link"), "{}", err);
+    assert_eq!(fs::read_dir(&outside).unwrap().count(), 0);
+}
+
+#[test]
+fn entries_are_never_overwritten() {
+    let dir = work_dir("duplicates");
+    let archive = tar(&[
+        Entry::File("file", b"first"),
+        Entry::File("file", b"second"),
+    ]);
+    let err = extract_err(&dir, "duplicates.tar", &archive);
+    assert!(err.contains("exists"), "{}", err);
+
+    // nor is the target itself
+    let archive_path = dir.join("file.tar");
+    fs::write(&archive_path, tar(&[Entry::File("file", b"x")])).unwrap();
+    fs::create_dir(dir.join("existing")).unwrap();
+    assert!(extract(&archive_path, &dir.join("existing"), &LIMIT).is_err());
+}
+
+#[test]
+fn sizes_and_entries_are_bounded() {
+    let dir = work_dir("sizes");
+    let big = vec![0u8; LIMIT.max_size as usize + 1];
+
+    let err = extract_err(&dir, "big.tar", &tar(&[Entry::File("big", &big)]));
+    assert!(err.contains("more than 1024 bytes"), "{}", err);
+    let err = extract_err(&dir, "big.zip", &zip("big", &big));
+    assert!(err.contains("more than 1024 bytes"), "{}", err);
+
+    // the limit covers all entries together
+    let half = vec![0u8; LIMIT.max_size as usize / 2 + 1];
+    let archive = tar(&[Entry::File("a", &half), Entry::File("b", &half)]);
+    let err = extract_err(&dir, "halves.tar", &archive);
+    assert!(err.contains("more than 1024 bytes"), "{}", err);
+
+    let names: Vec<String> = (0..=LIMIT.max_entries).map(|i| i.to_string()).collect();
+    let entries: Vec<Entry> = names.iter().map(|name| Entry::File(name, b"")).collect();
+    let err = extract_err(&dir, "entries.tar", &tar(&entries));
+    assert!(err.contains("more than 16 entries"), "{}", err);
+}
+
+#[test]
+fn zip_declaring_a_smaller_size_is_bounded() {
+    let dir = work_dir("zip");
+    // deflates to far less than the limit
+    let mut archive = zip("bomb", &vec![0u8; 1 << 20]);
+
+    // the uncompressed size of the local and central directory headers
+    for (signature, offset) in [(0x04034b50u32, 22), (0x02014b50, 24)] {
+        let start = archive
+            .windows(4)
+            .position(|window| window == signature.to_le_bytes())
+            .unwrap();
+        archive[start + offset..start + offset + 4].copy_from_slice(&10u32.to_le_bytes());
+    }
+    assert!(archive.len() < LIMIT.max_size as usize * 4);
+
+    let err = extract_err(&dir, "bomb.zip", &archive);
+    assert!(err.contains("more than 1024 bytes"), "{}", err);
+}
+
+#[test]
+fn safe_archive_is_extracted() {
+    let dir = work_dir("safe");
+    let archive_path = dir.join("safe.tar");
+    let archive = tar(&[Entry::File("./a/b.c", b"int x;"), Entry::File("c.c", b"")]);
+    fs::write(&archive_path, archive).unwrap();
+
+    let files = extract(&archive_path, &dir.join("target"), &LIMIT).unwrap();
+    assert_eq!(files, [PathBuf::from("a/b.c"), PathBuf::from("c.c")]);
+    assert_eq!(fs::read(dir.join("target/a/b.c")).unwrap(), b"int x;");
+}
+
+fn build(dir: &Path, extra_args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_sandbox"))
+        .args(["build", "-l", "c"])
+        .arg("-i")
+        .arg(dir.join("main.tar"))
+        .arg("-o")
+        .arg(dir.join("main"))
+        .args(extra_args)
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn archive_input_is_extracted_into_a_fresh_directory() {
+    let dir = work_dir("build");
+    let archive = tar(&[Entry::File("main.c", b"int main(void) { return 0; }\n")]);
+    fs::write(dir.join("main.tar"), archive).unwrap();
+
+    // one left beside the output, as by a build that died, is not in the way
+    fs::create_dir(dir.join("main.src")).unwrap();
+    for _ in 0..2 {
+        let output = build(&dir, &[]);
+        let errors = String::from_utf8_lossy(&output.stderr);
+        assert_eq!(
+            String::from_utf8_lossy(&output.stdout),
+            "ok\n",
+            "{}",
+            errors
+        );
+    }
+    let mut entries: Vec<_> = fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name())
+        .collect();
+    entries.sort();
+    assert_eq!(entries, ["main", "main.src", "main.tar"]);
+
+    let extract_dir = dir.join("main.src");
+    let output = build(&dir, &["--extract-dir", extract_dir.to_str().unwrap()]);
+    let errors = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "no\n");
+    assert!(errors.contains("already exists"), "{}", errors);
+    assert!(!errors.contains("panicked"), "{}", errors);
+}
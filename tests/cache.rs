@@ -0,0 +1,80 @@
+mod common;
+
+use std::{fs, path::Path, process::Command};
+
+use common::{build_stub, work_dir};
+
+// returns what the sandbox printed, which tells cache hits apart
+fn build(dir: &Path) -> String {
+    let output = Command::new(env!("CARGO_BIN_EXE_sandbox"))
+        .args(["build", "-l", "c"])
+        .arg("-i")
+        .arg(dir.join("main.c"))
+        .arg("-o")
+        .arg(dir.join("main"))
+        .arg("--grader")
+        .arg(dir.join("grader"))
+        .arg("--cache-dir")
+        .arg(dir.join("cache"))
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "build failed");
+
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+fn run(dir: &Path) -> String {
+    let output = Command::new(dir.join("main")).output().unwrap();
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+#[test]
+fn changed_grader_header_misses_the_cache() {
+    let dir = work_dir("header");
+    let grader = dir.join("grader");
+    fs::create_dir_all(&grader).unwrap();
+    fs::write(
+        dir.join("main.c"),
+        "void report(void);\nint main(void) {\n    report();\n    return 0;\n}\n",
+    )
+    .unwrap();
+    fs::write(
+        grader.join("grader.c"),
+        "#include <stdio.h>\n#include \"grader.h\"\nvoid report(void) {\n    printf(\"%d\\n\", VALUE);\n}\n",
+    )
+    .unwrap();
+
+    fs::write(grader.join("grader.h"), "#define VALUE 1\n").unwrap();
+    assert!(!build(&dir).contains("compile cache hit"));
+    assert!(build(&dir).contains("compile cache hit"));
+    assert_eq!(run(&dir), "1\n");
+
+    // only the grader includes it, so nothing else that is hashed changes
+    fs::write(grader.join("grader.h"), "#define VALUE 2\n").unwrap();
+    assert!(!build(&dir).contains("compile cache hit"));
+    assert_eq!(run(&dir), "2\n");
+}
+
+#[test]
+fn killed_compile_is_not_cached() {
+    let dir = work_dir("killed");
+    let bin = dir.join("root").join("usr").join("bin");
+    fs::create_dir_all(&bin).unwrap();
+    fs::write(dir.join("root").join("main.c"), "int main(void) {}\n").unwrap();
+    // a compiler that dies of SIGKILL, as if by a limit, in place of gcc
+    let compiler = build_stub(&dir, "namespace.c", &["-static", "-DSIGNAL=SIGKILL"]);
+    fs::rename(compiler, bin.join("gcc")).unwrap();
+
+    for _ in 0..2 {
+        let output = Command::new(env!("CARGO_BIN_EXE_sandbox"))
+            .args(["build", "-l", "c", "-i", "/main.c", "-o", "/main"])
+            .arg("--rootdir")
+            .arg(dir.join("root"))
+            .arg("--cache-dir")
+            .arg(dir.join("cache"))
+            .output()
+            .unwrap();
+        let output = String::from_utf8_lossy(&output.stdout);
+        assert_eq!(output, "no\n");
+    }
+}
@@ -0,0 +1,20 @@
+// what the command line prints of a run, past what the library returns
+mod common;
+
+use common::{build_stub, run, work_dir};
+
+#[test]
+fn captured_stderr_is_printed() {
+    let dir = work_dir("stderr");
+    let binary = build_stub(&dir, "stderr.c", &[]);
+
+    let result = run(&dir, &binary, None, &["--capture-stderr", "100"]);
+    assert!(result.success, "{}", result.report);
+    assert_eq!(result.errors, "to stderr\n");
+    assert_eq!(result.output, "to stdout\n");
+
+    // the cut is marked, so that it is not taken for all the program wrote
+    let result = run(&dir, &binary, None, &["--capture-stderr", "3"]);
+    assert!(result.success, "{}", result.report);
+    assert_eq!(result.errors, "to \n[stderr truncated]\n");
+}
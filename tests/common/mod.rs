@@ -0,0 +1,107 @@
+// fixtures of the integration tests, each of which builds this module on its own and uses only
+// part of it
+#![allow(dead_code)]
+
+use std::{
+    fs,
+    io::{self, Write},
+    ops::Deref,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+pub struct RunOutput {
+    pub success: bool,
+    // what the sandbox printed, including the verdict
+    pub report: String,
+    // what the sandbox wrote to stderr, including what it kept of the program's
+    pub errors: String,
+    // what the program wrote to stdout
+    pub output: String,
+}
+
+// a fresh directory of the test binary's own, removed once the test is done with it, passed
+// or not
+pub struct WorkDir(PathBuf);
+
+impl Deref for WorkDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl AsRef<Path> for WorkDir {
+    fn as_ref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for WorkDir {
+    fn drop(&mut self) {
+        fs::remove_dir_all(&self.0).ok();
+    }
+}
+
+pub fn work_dir(name: &str) -> WorkDir {
+    let dir = std::env::temp_dir().join(format!(
+        "sandbox-{}-{}-{}",
+        env!("CARGO_CRATE_NAME"),
+        std::process::id(),
+        name
+    ));
+    fs::remove_dir_all(&dir).ok();
+    fs::create_dir_all(&dir).unwrap();
+    WorkDir(dir)
+}
+
+// builds tests/stubs/<stub> into dir, named after the stub without its extension
+pub fn build_stub(dir: &Path, stub: &str, flags: &[&str]) -> PathBuf {
+    let source = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("stubs")
+        .join(stub);
+    let binary = dir.join(source.file_stem().unwrap());
+
+    let status = Command::new("gcc")
+        .args(flags)
+        .arg(&source)
+        .arg("-o")
+        .arg(&binary)
+        .status()
+        .unwrap();
+    assert!(status.success(), "failed to build {}", stub);
+
+    binary
+}
+
+// runs file as a C program, judged against answer when given
+pub fn run(dir: &Path, file: &Path, answer: Option<&str>, extra_args: &[&str]) -> RunOutput {
+    let output_path = dir.join("output.txt");
+    let mut command = Command::new(env!("CARGO_BIN_EXE_sandbox"));
+    command
+        .args(["run", "-l", "c", "--memory", "268435456", "--time", "1"])
+        .arg("-f")
+        .arg(file)
+        .arg("-o")
+        .arg(&output_path);
+    if let Some(answer) = answer {
+        let answer_path = dir.join("answer.txt");
+        fs::write(&answer_path, answer).unwrap();
+        command.arg("-a").arg(answer_path);
+    }
+    let output = command.args(extra_args).output().unwrap();
+
+    RunOutput {
+        success: output.status.success(),
+        report: String::from_utf8_lossy(&output.stdout).into_owned(),
+        errors: String::from_utf8_lossy(&output.stderr).into_owned(),
+        output: fs::read_to_string(&output_path).unwrap_or_default(),
+    }
+}
+
+// written past the test harness's capture, so that a check this machine cannot make shows up
+pub fn skip(test: &str, reason: &str) {
+    writeln!(io::stderr(), "{}: skipped, {}", test, reason).ok();
+}
@@ -0,0 +1,90 @@
+// system calls from the i386 and x32 ABIs must not slip past filters written for x86_64
+#![cfg(target_arch = "x86_64")]
+
+mod common;
+
+use std::fs;
+
+use common::{build_stub, run, work_dir, RunOutput};
+
+#[test]
+fn int80_from_64bit_process_is_a_violation() {
+    let dir = work_dir("int80");
+    let binary = build_stub(&dir, "int80_socket.c", &[]);
+
+    let RunOutput { report, output, .. } = run(&dir, &binary, Some(""), &[]);
+    assert!(report.contains("SecurityViolation"), "{}", report);
+    assert!(
+        report.contains("syscall: \"socket\", number: 359"),
+        "{}",
+        report
+    );
+    assert!(!output.contains("escaped"), "{}", output);
+}
+
+#[test]
+fn x32_call_is_a_violation() {
+    let dir = work_dir("x32");
+    let binary = build_stub(&dir, "x32_socket.c", &[]);
+
+    let RunOutput { report, output, .. } = run(&dir, &binary, Some(""), &[]);
+    assert!(report.contains("SecurityViolation"), "{}", report);
+    assert!(report.contains("syscall: \"socket\""), "{}", report);
+    assert!(!output.contains("escaped"), "{}", output);
+}
+
+#[test]
+fn i386_binary_is_a_violation() {
+    let dir = work_dir("i386");
+    let binary = build_stub(&dir, "i386_socket.S", &["-m32", "-nostdlib", "-static"]);
+
+    let RunOutput { report, output, .. } = run(&dir, &binary, Some(""), &[]);
+    assert!(report.contains("SecurityViolation"), "{}", report);
+    assert!(
+        report.contains("syscall: \"socket\", number: 359"),
+        "{}",
+        report
+    );
+    assert!(!output.contains("escaped"), "{}", output);
+}
+
+#[test]
+fn listed_architectures_share_the_rules() {
+    let dir = work_dir("mirror");
+    let profile = dir.join("profile.toml");
+    fs::write(
+        &profile,
+        r#"
+architectures = ["x86_64", "x86"]
+
+[[syscalls]]
+names = ["execve", "write", "exit", "exit_group"]
+action = "allow"
+"#,
+    )
+    .unwrap();
+
+    let hello = build_stub(&dir, "i386_hello.S", &["-m32", "-nostdlib", "-static"]);
+    let RunOutput { report, output, .. } = run(
+        &dir,
+        &hello,
+        Some("hello\n"),
+        &["--seccomp-profile", profile.to_str().unwrap()],
+    );
+    assert!(report.contains("Accepted"), "{}", report);
+    assert_eq!(output, "hello\n");
+
+    let socket = build_stub(&dir, "i386_socket.S", &["-m32", "-nostdlib", "-static"]);
+    let RunOutput { report, output, .. } = run(
+        &dir,
+        &socket,
+        Some(""),
+        &["--seccomp-profile", profile.to_str().unwrap()],
+    );
+    assert!(
+        report.contains("syscall: \"socket\", number: 359"),
+        "{}",
+        report
+    );
+    assert!(!output.contains("escaped"), "{}", output);
+}
@@ -0,0 +1,40 @@
+// the program only keeps the descriptors it was meant to have
+mod common;
+
+use std::{
+    fs,
+    os::unix::{io::AsRawFd, process::CommandExt},
+    process::Command,
+};
+
+use nix::libc;
+
+use common::{build_stub, work_dir};
+
+#[test]
+fn only_passed_descriptors_are_inherited() {
+    let dir = work_dir("fds");
+    let binary = build_stub(&dir, "fds.c", &[]);
+    let output_path = dir.join("output.txt");
+    let file = fs::File::open(&binary).unwrap();
+    let fd = file.as_raw_fd();
+
+    let mut command = Command::new(env!("CARGO_BIN_EXE_sandbox"));
+    command
+        .args(["run", "-l", "c", "--memory", "268435456", "--time", "1"])
+        .args(["--seccomp-default", "allow", "--pass-fd", "7", "-f"])
+        .arg(&binary)
+        .arg("-o")
+        .arg(&output_path);
+    // both inherited by the sandbox, as dup2 leaves close-on-exec off
+    unsafe {
+        command.pre_exec(move || {
+            libc::dup2(fd, 6);
+            libc::dup2(fd, 7);
+            Ok(())
+        })
+    };
+    let output = command.output().unwrap();
+    assert!(output.status.success());
+    assert_eq!(fs::read_to_string(&output_path).unwrap(), "0\n1\n2\n7\n");
+}
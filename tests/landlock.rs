@@ -0,0 +1,56 @@
+// the program writes where it was given to, not wherever the judge happens to run
+mod common;
+
+use std::{fs, path::Path, process::Command};
+
+use common::{build_stub, skip, work_dir};
+use sandbox::landlock::abi;
+
+// runs the stub from dir, returning what it wrote to stdout and the run report
+fn run_in(dir: &Path, binary: &Path, extra_args: &[&str]) -> (String, serde_json::Value) {
+    let (output_path, report_path) = (dir.join("output.txt"), dir.join("report.json"));
+    let result = Command::new(env!("CARGO_BIN_EXE_sandbox"))
+        .current_dir(dir)
+        .args(["run", "-l", "c", "--memory", "268435456", "--time", "1"])
+        .args(["--landlock", "--seccomp-default", "allow", "-f"])
+        .arg(binary)
+        .arg("-o")
+        .arg(&output_path)
+        .arg("--report")
+        .arg(&report_path)
+        .args(extra_args)
+        .output()
+        .unwrap();
+    assert!(result.status.success());
+
+    (
+        fs::read_to_string(output_path).unwrap_or_default(),
+        serde_json::from_slice(&fs::read(report_path).unwrap()).unwrap(),
+    )
+}
+
+#[test]
+fn working_directory_is_writable_only_when_given() {
+    let dir = work_dir("workdir");
+    let binary = build_stub(&dir, "write.c", &["-DTARGET=\"file\""]);
+
+    let (output, report) = run_in(&dir, &binary, &[]);
+    let abi = match abi() {
+        Some(abi) => abi,
+        None => {
+            // the run goes on without it, which the report must own up to
+            assert_eq!(report["landlock"], "unsupported", "{}", report);
+            skip(
+                "working_directory_is_writable_only_when_given",
+                "no Landlock",
+            );
+            return;
+        }
+    };
+    assert_eq!(output, "Permission denied\n");
+    assert_eq!(report["landlock"]["enforced"]["abi"], abi, "{}", report);
+    assert!(!dir.join("file").exists());
+
+    let (output, _) = run_in(&dir, &binary, &["--workdir", dir.to_str().unwrap()]);
+    assert_eq!(output, "written\n");
+}
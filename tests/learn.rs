@@ -0,0 +1,47 @@
+// a learned profile lets the program do what it did while learning, and nothing else
+mod common;
+
+use std::{fs, process::Command};
+
+use common::{build_stub, run, work_dir};
+
+#[test]
+fn learned_profile_allows_what_was_recorded() {
+    let dir = work_dir("learn");
+    let binary = build_stub(&dir, "forks.c", &[]);
+    let profile = dir.join("profile.toml");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_sandbox"))
+        .args([
+            "profile",
+            "learn",
+            "--memory",
+            "268435456",
+            "--time",
+            "1",
+            "-f",
+        ])
+        .arg(&binary)
+        .arg("-p")
+        .arg(&profile)
+        .arg("-o")
+        .arg(dir.join("learned.txt"))
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let rules = fs::read_to_string(&profile).unwrap();
+    assert!(rules.contains("\"clone\""), "{}", rules);
+
+    // the builtin profile has no room for its children
+    let result = run(&dir, &binary, Some("20\n"), &[]);
+    assert!(
+        result.report.contains("syscall: \"clone\""),
+        "{}",
+        result.report
+    );
+
+    let args = ["--seccomp-profile", profile.to_str().unwrap()];
+    let result = run(&dir, &binary, Some("20\n"), &args);
+    assert!(result.report.contains("Accepted"), "{}", result.report);
+    assert_eq!(result.output, "20\n");
+}
@@ -0,0 +1,194 @@
+// input and output go through pipes, so the library needs no files for either
+mod common;
+
+use std::fs;
+
+use common::{build_stub, skip, work_dir};
+use sandbox::{
+    executor::{execute_report, ExecuteOption, ResourceLimit},
+    judge::ResultKind,
+    process::Directory,
+    seccomp::{SyscallFilter, SyscallFilterAction},
+    trace::TraceMode,
+};
+
+fn limits() -> Option<ResourceLimit> {
+    Some(ResourceLimit {
+        memory: 256 << 20,
+        time: 5,
+        processes: None,
+        output: None,
+    })
+}
+
+#[test]
+fn input_and_output_stay_in_memory() {
+    let report = execute_report(
+        "/bin/cat",
+        vec![],
+        ExecuteOption {
+            limits: limits(),
+            input: Some(b"1 2\n3\n".to_vec()),
+            output_limit: Some(1024),
+            answer: Some(b"1 2\n3\n".to_vec()),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+
+    let stdout = report.stdout.unwrap();
+    assert_eq!(stdout.data, b"1 2\n3\n");
+    assert!(!stdout.truncated);
+    assert!(matches!(report.result, Some(ResultKind::Accepted)));
+}
+
+#[test]
+fn output_past_the_limit_is_dropped() {
+    // more than a pipe holds, which the program must not block on
+    let input = vec![b'x'; 1 << 20];
+    let report = execute_report(
+        "/bin/cat",
+        vec![],
+        ExecuteOption {
+            limits: limits(),
+            input: Some(input),
+            output_limit: Some(100),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+
+    assert_eq!(report.status, 0);
+    let stdout = report.stdout.unwrap();
+    assert_eq!(stdout.data, vec![b'x'; 100]);
+    assert!(stdout.truncated);
+}
+
+#[test]
+fn processes_are_counted_for_root_as_well() {
+    // RLIMIT_NPROC, all there is without a pids cgroup, does not hold for root
+    if sandbox::cgroup::PidsCgroup::new(1).is_err() && nix::unistd::geteuid().is_root() {
+        skip("processes_are_counted_for_root_as_well", "no pids cgroup");
+        return;
+    }
+    let dir = work_dir("forks");
+    let binary = build_stub(&dir, "forks.c", &[]);
+    let binary = binary.to_str().unwrap();
+    let report = execute_report(
+        binary,
+        vec![binary],
+        ExecuteOption {
+            limits: Some(ResourceLimit {
+                processes: Some(5),
+                ..limits().unwrap()
+            }),
+            output_limit: Some(1024),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+
+    let started: u32 = String::from_utf8_lossy(&report.stdout.unwrap().data)
+        .trim()
+        .parse()
+        .unwrap();
+    assert!(started < 5, "{} processes started", started);
+}
+
+#[test]
+fn children_of_the_caller_are_left_alone() {
+    let mut child = std::process::Command::new("/bin/true").spawn().unwrap();
+    std::thread::sleep(std::time::Duration::from_millis(100));
+
+    // a traced run waits for the program's own threads and processes only
+    let report = execute_report(
+        "/bin/cat",
+        vec![],
+        ExecuteOption {
+            limits: limits(),
+            input: Some(b"x".to_vec()),
+            output_limit: Some(1024),
+            syscall_filter: Some(SyscallFilter::new(SyscallFilterAction::Allow)),
+            trace: Some(TraceMode::Learn),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+
+    assert_eq!(report.status, 0);
+    assert!(child.wait().unwrap().success());
+}
+
+#[test]
+fn time_below_a_second_is_counted() {
+    let report = execute_report(
+        "/bin/sh",
+        vec![
+            "/bin/sh",
+            "-c",
+            "i=0; while [ $i -lt 100000 ]; do i=$((i+1)); done",
+        ],
+        ExecuteOption {
+            limits: limits(),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+
+    assert_eq!(report.status, 0);
+    assert!(
+        report.user_time_ms + report.cpu_time_ms >= 20,
+        "{:?}",
+        report
+    );
+}
+
+#[test]
+fn child_never_returns_to_the_caller() {
+    let dir = work_dir("return");
+    let caller = std::process::id();
+    let root = Directory {
+        working_dir: None,
+        root_dir: Some(dir.join("missing")),
+        mounts: vec![],
+        overlay: None,
+        landlock: false,
+    };
+
+    // a failed exec, and a panic on the way to it
+    for (binary, directory) in [("/missing", None), ("/bin/true", Some(root))] {
+        let report = execute_report(
+            binary,
+            vec![binary],
+            ExecuteOption {
+                limits: limits(),
+                directory,
+                ..Default::default()
+            },
+        );
+        if std::process::id() != caller {
+            fs::write(dir.join("returned"), binary).unwrap();
+            std::process::exit(0);
+        }
+
+        assert_ne!(report.unwrap().status, 0);
+        assert!(!dir.join("returned").exists(), "{}", binary);
+    }
+}
+
+#[test]
+fn answer_is_judged_without_limits() {
+    let report = execute_report(
+        "/bin/cat",
+        vec![],
+        ExecuteOption {
+            input: Some(b"1\n".to_vec()),
+            output_limit: Some(1024),
+            answer: Some(b"1\n".to_vec()),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+
+    assert!(matches!(report.result, Some(ResultKind::Accepted)));
+}
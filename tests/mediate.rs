@@ -0,0 +1,26 @@
+// the supervisor opens files for the program as root, but on the program's behalf only
+mod common;
+
+use std::{fs, os::unix::fs::PermissionsExt};
+
+use common::{build_stub, run, work_dir};
+
+#[test]
+fn mediated_open_keeps_the_program_user() {
+    let dir = work_dir("user");
+    let secret = dir.join("secret.txt");
+    fs::write(&secret, "secret").unwrap();
+    fs::set_permissions(&secret, fs::Permissions::from_mode(0o600)).unwrap();
+    let define = format!("-DFILE_PATH=\"{}\"", secret.display());
+    let binary = build_stub(&dir, "open.c", &[&define]);
+
+    let args = ["--user", "65534", "--mediate-default", "allow"];
+    let result = run(&dir, &binary, None, &args);
+    assert!(result.success, "{}", result.report);
+    assert_eq!(result.output, "Permission denied\n");
+
+    // the same file opens once the program may open it
+    fs::set_permissions(&secret, fs::Permissions::from_mode(0o666)).unwrap();
+    let result = run(&dir, &binary, None, &args);
+    assert_eq!(result.output, "opened\n");
+}
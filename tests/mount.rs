@@ -0,0 +1,96 @@
+// a read-only layout stays read-only all the way down, and shared directories are not written to
+// in making it
+mod common;
+
+use std::{fs, path::Path, process::Command};
+
+use common::{build_stub, work_dir};
+
+// runs the sandbox in a mount namespace of its own, with a tmpfs mounted at submount first
+fn run_with_submount(dir: &Path, submount: &Path, args: &[&str]) -> (bool, String) {
+    let output_path = dir.join("output.txt");
+    let output = Command::new("unshare")
+        .args(["-m", "--propagation", "private", "sh", "-c"])
+        .arg("mount -t tmpfs tmpfs \"$0\" && exec \"$@\"")
+        .arg(submount)
+        .arg(env!("CARGO_BIN_EXE_sandbox"))
+        .args(["run", "-l", "c", "--memory", "268435456", "--time", "1"])
+        .args(["--seccomp-default", "allow", "-o"])
+        .arg(&output_path)
+        .args(args)
+        .output()
+        .unwrap();
+
+    (
+        output.status.success(),
+        fs::read_to_string(&output_path).unwrap_or_default(),
+    )
+}
+
+#[test]
+fn submounts_of_a_read_only_bind_are_read_only() {
+    let dir = work_dir("submount");
+    let data = dir.join("data");
+    fs::create_dir_all(data.join("sub")).unwrap();
+    let binary = build_stub(&dir, "write.c", &["-static", "-DTARGET=\"/data/sub/file\""]);
+    fs::rename(binary, data.join("write")).unwrap();
+
+    let mount = format!("ro:{}:/data", data.display());
+    let args = ["-f", "/data/write", "--mount", &mount];
+    let (success, output) = run_with_submount(&dir, &data.join("sub"), &args);
+    assert!(success);
+    assert_eq!(output, "Read-only file system\n");
+
+    // the same, writable
+    let mount = format!("rw:{}:/data", data.display());
+    let args = ["-f", "/data/write", "--mount", &mount];
+    let (success, output) = run_with_submount(&dir, &data.join("sub"), &args);
+    assert!(success);
+    assert_eq!(output, "written\n");
+}
+
+#[test]
+fn submounts_of_a_read_only_root_dir_are_read_only() {
+    let dir = work_dir("rootdir");
+    let root = dir.join("root");
+    fs::create_dir_all(root.join("sub")).unwrap();
+    fs::create_dir_all(root.join("tmp")).unwrap();
+    let binary = build_stub(&dir, "write.c", &["-static", "-DTARGET=\"/sub/file\""]);
+    fs::rename(binary, root.join("write")).unwrap();
+
+    let root_dir = root.to_str().unwrap();
+    let args = [
+        "-f",
+        "/write",
+        "--rootdir",
+        root_dir,
+        "--mount",
+        "tmpfs:/tmp",
+    ];
+    let (success, output) = run_with_submount(&dir, &root.join("sub"), &args);
+    assert!(success);
+    assert_eq!(output, "Read-only file system\n");
+}
+
+#[test]
+fn mount_points_are_not_made_in_a_root_dir() {
+    let dir = work_dir("mount-point");
+    let root = dir.join("root");
+    fs::create_dir_all(&root).unwrap();
+    let binary = build_stub(&dir, "write.c", &["-static", "-DTARGET=\"/tmp/file\""]);
+    fs::rename(binary, root.join("write")).unwrap();
+
+    let root_dir = root.to_str().unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_sandbox"))
+        .args(["run", "-l", "c", "--memory", "268435456", "--time", "1"])
+        .args(["-f", "/write", "--seccomp-default", "allow"])
+        .args(["--rootdir", root_dir, "--mount", "tmpfs:/tmp"])
+        .output()
+        .unwrap();
+    assert!(
+        String::from_utf8_lossy(&output.stderr).contains("nothing to mount /tmp over"),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert!(!root.join("tmp").exists());
+}
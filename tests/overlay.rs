@@ -0,0 +1,45 @@
+// the program writes over a shared root without changing it
+mod common;
+
+use std::{fs, process::Command};
+
+use common::{build_stub, run, work_dir};
+
+#[test]
+fn overlay_keeps_the_root_unchanged() {
+    let dir = work_dir("overlay");
+    let root = dir.join("root");
+    fs::create_dir_all(&root).unwrap();
+    let stub = build_stub(&dir, "write.c", &["-static", "-DTARGET=\"/written\""]);
+    fs::rename(stub, root.join("write")).unwrap();
+    let archive = dir.join("upper.tar");
+
+    let args = [
+        "--seccomp-default",
+        "allow",
+        "--rootdir",
+        root.to_str().unwrap(),
+        "--overlay",
+        "--overlay-archive",
+        archive.to_str().unwrap(),
+    ];
+    let result = run(&dir, "/write".as_ref(), None, &args);
+    assert!(result.success, "{}", result.report);
+    assert_eq!(result.output, "written\n");
+    assert!(!root.join("written").exists());
+
+    // what it wrote is only kept in the archive
+    let listing = Command::new("tar")
+        .arg("-tf")
+        .arg(&archive)
+        .output()
+        .unwrap();
+    let listing = String::from_utf8_lossy(&listing.stdout);
+    assert!(
+        listing
+            .lines()
+            .any(|entry| entry.trim_start_matches("./") == "written"),
+        "{}",
+        listing
+    );
+}
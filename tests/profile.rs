@@ -0,0 +1,39 @@
+// what the builtin run profiles let a submission do beyond its own computation
+mod common;
+
+use common::{build_stub, run, work_dir, RunOutput};
+
+#[test]
+fn only_the_first_exec_is_allowed() {
+    let dir = work_dir("exec");
+    let binary = build_stub(&dir, "exec.c", &[]);
+
+    let RunOutput { report, output, .. } = run(&dir, &binary, Some(""), &[]);
+    assert!(report.contains("SecurityViolation"), "{}", report);
+    assert!(report.contains("syscall: \"execve\""), "{}", report);
+    assert_eq!(output, "started\n");
+}
+
+#[test]
+fn only_the_program_itself_is_limited_and_signalled() {
+    let dir = work_dir("parent");
+    for (flag, syscall) in [("-DLIMIT", "prlimit64"), ("-DSIGNAL", "tgkill")] {
+        let binary = build_stub(&dir, "parent.c", &[flag]);
+
+        let RunOutput {
+            success,
+            report,
+            output,
+            ..
+        } = run(&dir, &binary, Some(""), &[]);
+        assert!(success, "{}", report);
+        let violation = format!("syscall: \"{}\"", syscall);
+        assert!(report.contains(&violation), "{}", report);
+        assert_eq!(output, "started\n");
+    }
+
+    let binary = build_stub(&dir, "parent.c", &[]);
+    let RunOutput { report, output, .. } = run(&dir, &binary, Some(""), &[]);
+    assert!(report.contains("RuntimeError"), "{}", report);
+    assert_eq!(output, "started\n");
+}
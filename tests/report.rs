@@ -0,0 +1,30 @@
+// what the run report tells of the program
+mod common;
+
+use std::fs;
+
+use common::{build_stub, run, work_dir};
+
+#[test]
+fn syscall_stats_count_the_calls_made() {
+    let dir = work_dir("stats");
+    let binary = build_stub(&dir, "stderr.c", &[]);
+    let report_path = dir.join("report.json");
+    let report_arg = report_path.to_str().unwrap();
+
+    let result = run(&dir, &binary, None, &["--report", report_arg]);
+    assert!(result.success, "{}", result.report);
+    let report: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(&report_path).unwrap()).unwrap();
+    assert!(report.get("syscalls").is_none(), "{}", report);
+
+    let args = ["--report", report_arg, "--syscall-stats"];
+    let result = run(&dir, &binary, None, &args);
+    assert!(result.success, "{}", result.report);
+    let report: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(&report_path).unwrap()).unwrap();
+    let syscalls = &report["syscalls"];
+    // one line to stderr and, flushed at exit, one to stdout
+    assert_eq!(syscalls["write"], 2, "{}", report);
+    assert_eq!(syscalls["exit_group"], 1, "{}", report);
+}
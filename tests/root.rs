@@ -0,0 +1,117 @@
+// a program in its own root must not reach the host's files, not even by walking up with ..
+mod common;
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use common::{build_stub, run, work_dir};
+
+// nothing to load the stub from in an empty root
+const STATIC: &[&str] = &["-static"];
+
+// the stub tries to open a secret of the host, whose path it is built with
+fn build_escape(dir: &Path) -> PathBuf {
+    let secret = dir.join("secret.txt");
+    fs::write(&secret, "secret").unwrap();
+    let define = format!("-DSECRET=\"{}\"", secret.display());
+    build_stub(dir, "escape.c", &[STATIC, &[define.as_str()]].concat())
+}
+
+// returns what the stub wrote to stdout
+fn escape(dir: &Path, file: &str, extra_args: &[&str]) -> String {
+    // only the file system is under test
+    let args = [&["--seccomp-default", "allow"], extra_args].concat();
+    let result = run(dir, Path::new(file), None, &args);
+    assert!(result.success, "{}", result.report);
+
+    result.output
+}
+
+#[test]
+fn root_dir_cannot_be_escaped() {
+    let dir = work_dir("rootdir");
+    let root = dir.join("root");
+    fs::create_dir_all(root.join("work")).unwrap();
+    fs::rename(build_escape(&dir), root.join("escape")).unwrap();
+
+    let output = escape(
+        &dir,
+        "/escape",
+        &["--rootdir", root.to_str().unwrap(), "--workdir", "/work"],
+    );
+    assert_eq!(output, "cwd /work\ncontained\n");
+}
+
+#[test]
+fn mount_layout_cannot_be_escaped() {
+    let dir = work_dir("layout");
+    let bin = dir.join("bin");
+    fs::create_dir_all(&bin).unwrap();
+    fs::rename(build_escape(&dir), bin.join("escape")).unwrap();
+
+    let mount = format!("ro:{}:/work", bin.display());
+    let output = escape(
+        &dir,
+        "/work/escape",
+        &[
+            "--mount",
+            &mount,
+            "--mount",
+            "tmpfs:/tmp",
+            "--workdir",
+            "/work",
+        ],
+    );
+    assert_eq!(output, "cwd /work\ncontained\n");
+}
+
+#[test]
+fn namespaces_isolate_the_program() {
+    let dir = work_dir("namespaces");
+    let binary = build_stub(&dir, "namespace.c", &[]);
+
+    let args = ["--seccomp-default", "allow", "--namespaces", "all"];
+    let result = run(&dir, &binary, None, &args);
+    assert!(result.success, "{}", result.report);
+    // the sandbox's init is 1, and the orphan is reaped by it
+    assert_eq!(result.output, "pid 2\ninterface lo\nzombies 0\n");
+}
+
+#[test]
+fn signal_is_seen_through_the_pid_namespace() {
+    let dir = work_dir("signal");
+    let binary = build_stub(&dir, "namespace.c", &["-DSIGNAL=SIGSEGV"]);
+    let report = dir.join("report.json");
+
+    let args = [
+        "--seccomp-default",
+        "allow",
+        "--namespaces",
+        "pid,mount",
+        "--report",
+        report.to_str().unwrap(),
+    ];
+    let result = run(&dir, &binary, None, &args);
+    assert!(result.success, "{}", result.report);
+    let report: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(&report).unwrap()).unwrap();
+    let status = report["status"].as_i64().unwrap() as i32;
+    assert!(nix::libc::WIFSIGNALED(status), "{}", status);
+    assert_eq!(nix::libc::WTERMSIG(status), nix::libc::SIGSEGV);
+}
+
+#[test]
+fn another_user_has_no_capabilities_left() {
+    let dir = work_dir("privileges");
+    let binary = build_stub(&dir, "privileges.c", &[]);
+
+    let args = ["--seccomp-default", "allow", "--user", "65534"];
+    let result = run(&dir, &binary, None, &args);
+    assert!(result.success, "{}", result.report);
+    assert_eq!(
+        result.output,
+        "CapEff:\t0000000000000000\nCapBnd:\t0000000000000000\nsetuid Operation not permitted\n"
+    );
+}
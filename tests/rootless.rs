@@ -0,0 +1,60 @@
+// an unprivileged user sandboxes from a user namespace of its own
+mod common;
+
+use std::{
+    fs,
+    os::unix::{fs::PermissionsExt, process::CommandExt},
+    process::Command,
+};
+
+use nix::unistd;
+
+use common::{build_stub, work_dir};
+
+// nobody, when the tests run as root
+const UNPRIVILEGED: u32 = 65534;
+
+#[test]
+fn unprivileged_user_runs_in_its_own_root() {
+    let dir = work_dir("rootless");
+    let bin = dir.join("bin");
+    fs::create_dir_all(&bin).unwrap();
+    fs::rename(
+        build_stub(&dir, "whoami.c", &["-static"]),
+        bin.join("whoami"),
+    )
+    .unwrap();
+    // the target directory of the build may be out of the user's reach
+    let sandbox = dir.join("sandbox");
+    fs::copy(env!("CARGO_BIN_EXE_sandbox"), &sandbox).unwrap();
+    fs::set_permissions(&dir, fs::Permissions::from_mode(0o777)).unwrap();
+
+    let output_path = dir.join("output.txt");
+    let mount = format!("ro:{}:/work", bin.display());
+    let mut command = Command::new(&sandbox);
+    command
+        .args(["run", "-l", "c", "--memory", "268435456", "--time", "1"])
+        .args(["--rootless", "--mount", &mount, "--mount", "proc"])
+        .args(["--workdir", "/work"])
+        .args(["-f", "/work/whoami", "-o"])
+        .arg(&output_path);
+    let uid = match unistd::geteuid().is_root() {
+        true => {
+            command.uid(UNPRIVILEGED).gid(UNPRIVILEGED);
+            UNPRIVILEGED
+        }
+        false => unistd::geteuid().as_raw(),
+    };
+    let output = command.output().unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    // root inside, and only that one user outside
+    assert_eq!(
+        fs::read_to_string(&output_path).unwrap(),
+        format!("uid 0\nmap 0 {} 1\n", uid)
+    );
+}
@@ -0,0 +1,22 @@
+#include <fcntl.h>
+#include <stdio.h>
+#include <unistd.h>
+
+int main(void) {
+    const char *secret = SECRET;
+    char cwd[4096];
+    int i;
+
+    if (getcwd(cwd, sizeof(cwd)) != NULL) {
+        printf("cwd %s\n", cwd);
+    }
+    for (i = 0; i < 64; i++) {
+        chdir("..");
+    }
+    if (open(secret, O_RDONLY) >= 0 || open(secret + 1, O_RDONLY) >= 0) {
+        printf("escaped\n");
+    } else {
+        printf("contained\n");
+    }
+    return 0;
+}
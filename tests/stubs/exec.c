@@ -0,0 +1,12 @@
+#include <stdio.h>
+#include <unistd.h>
+
+int main(void) {
+    char *argv[] = {"/bin/echo", "escaped", NULL};
+
+    printf("started\n");
+    fflush(stdout);
+    execv("/bin/echo", argv);
+    printf("contained\n");
+    return 0;
+}
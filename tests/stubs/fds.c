@@ -0,0 +1,17 @@
+#include <dirent.h>
+#include <stdio.h>
+#include <stdlib.h>
+
+int main(void) {
+    DIR *fds = opendir("/proc/self/fd");
+    struct dirent *entry;
+
+    while ((entry = readdir(fds)) != NULL) {
+        // the listing's own descriptor is not one the program was given
+        if (entry->d_name[0] != '.' && atoi(entry->d_name) != dirfd(fds)) {
+            printf("%s\n", entry->d_name);
+        }
+    }
+    closedir(fds);
+    return 0;
+}
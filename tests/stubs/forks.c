@@ -0,0 +1,20 @@
+#include <stdio.h>
+#include <unistd.h>
+
+int main(void) {
+    int started = 0;
+    int i;
+
+    for (i = 0; i < 20; i++) {
+        pid_t pid = fork();
+        if (pid == 0) {
+            sleep(1);
+            _exit(0);
+        }
+        if (pid > 0) {
+            started++;
+        }
+    }
+    printf("%d\n", started);
+    return 0;
+}
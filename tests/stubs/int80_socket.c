@@ -0,0 +1,10 @@
+#include <stdio.h>
+
+int main(void) {
+    long ret;
+
+    // i386 socket(AF_INET, SOCK_STREAM, 0) through the compat entry point
+    asm volatile("int $0x80" : "=a"(ret) : "a"(359), "b"(2), "c"(1), "d"(0) : "memory");
+    printf("escaped %ld\n", ret);
+    return 0;
+}
@@ -0,0 +1,71 @@
+#include <dirent.h>
+#include <signal.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+// processes of the namespace left as zombies
+static int zombies(void) {
+    DIR *proc = opendir("/proc");
+    struct dirent *entry;
+    int count = 0;
+
+    while (proc && (entry = readdir(proc)) != NULL) {
+        char path[64], stat[256];
+        FILE *file;
+
+        if (atoi(entry->d_name) <= 0) {
+            continue;
+        }
+        snprintf(path, sizeof(path), "/proc/%s/stat", entry->d_name);
+        file = fopen(path, "r");
+        if (file == NULL) {
+            continue;
+        }
+        if (fgets(stat, sizeof(stat), file) != NULL && strstr(stat, ") Z ") != NULL) {
+            count++;
+        }
+        fclose(file);
+    }
+    if (proc) {
+        closedir(proc);
+    }
+    return count;
+}
+
+int main(void) {
+#if defined(SIGNAL)
+    raise(SIGNAL);
+#else
+    char line[256];
+    FILE *dev = fopen("/proc/net/dev", "r");
+    pid_t child;
+
+    printf("pid %d\n", getpid());
+
+    // past the two header lines, one interface per line
+    fgets(line, sizeof(line), dev);
+    fgets(line, sizeof(line), dev);
+    while (fgets(line, sizeof(line), dev) != NULL) {
+        char name[32];
+        sscanf(line, " %31[^:]", name);
+        printf("interface %s\n", name);
+    }
+    fclose(dev);
+
+    // the grandchild is orphaned and left to the namespace's init
+    child = fork();
+    if (child == 0) {
+        if (fork() == 0) {
+            _exit(0);
+        }
+        _exit(0);
+    }
+    waitpid(child, NULL, 0);
+    usleep(200000);
+    printf("zombies %d\n", zombies());
+#endif
+    return 0;
+}
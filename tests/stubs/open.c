@@ -0,0 +1,14 @@
+#include <errno.h>
+#include <fcntl.h>
+#include <stdio.h>
+#include <string.h>
+
+int main(void) {
+    // a writable open, which the profile leaves to the supervisor
+    if (open(FILE_PATH, O_RDWR) >= 0) {
+        printf("opened\n");
+    } else {
+        printf("%s\n", strerror(errno));
+    }
+    return 0;
+}
@@ -0,0 +1,24 @@
+#define _GNU_SOURCE
+#include <signal.h>
+#include <stdio.h>
+#include <sys/resource.h>
+#include <sys/syscall.h>
+#include <unistd.h>
+
+int main(void) {
+    struct rlimit none = {0, 0};
+
+    printf("started\n");
+    fflush(stdout);
+#if defined(LIMIT)
+    prlimit(getppid(), RLIMIT_NOFILE, &none, NULL);
+#elif defined(SIGNAL)
+    syscall(SYS_tgkill, getppid(), getppid(), SIGKILL);
+#else
+    // its own limits and signals are still its to change
+    prlimit(0, RLIMIT_NOFILE, &none, NULL);
+    raise(SIGTERM);
+#endif
+    printf("contained\n");
+    return 0;
+}
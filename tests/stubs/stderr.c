@@ -0,0 +1,7 @@
+#include <stdio.h>
+
+int main(void) {
+    fputs("to stderr\n", stderr);
+    printf("to stdout\n");
+    return 0;
+}
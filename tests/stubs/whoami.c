@@ -0,0 +1,13 @@
+#include <stdio.h>
+#include <unistd.h>
+
+int main(void) {
+    unsigned int inside, outside, count;
+    FILE *map = fopen("/proc/self/uid_map", "r");
+
+    printf("uid %d\n", getuid());
+    if (map != NULL && fscanf(map, "%u %u %u", &inside, &outside, &count) == 3) {
+        printf("map %u %u %u\n", inside, outside, count);
+    }
+    return 0;
+}
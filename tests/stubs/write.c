@@ -0,0 +1,13 @@
+#include <errno.h>
+#include <fcntl.h>
+#include <stdio.h>
+#include <string.h>
+
+int main(void) {
+    if (open(TARGET, O_WRONLY | O_CREAT, 0644) >= 0) {
+        printf("written\n");
+    } else {
+        printf("%s\n", strerror(errno));
+    }
+    return 0;
+}
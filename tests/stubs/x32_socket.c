@@ -0,0 +1,13 @@
+#include <stdio.h>
+
+int main(void) {
+    long ret;
+
+    // x32 socket(AF_INET, SOCK_STREAM, 0)
+    asm volatile("syscall"
+                 : "=a"(ret)
+                 : "a"(0x40000000 | 41), "D"(2), "S"(1), "d"(0)
+                 : "rcx", "r11", "memory");
+    printf("escaped %ld\n", ret);
+    return 0;
+}